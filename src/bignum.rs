@@ -0,0 +1,284 @@
+//! Exact integer printing for the command line, behind the `bignum`
+//! feature. It is a printing aid rather than an arbitrary-precision mode:
+//! `exact` recomputes results made only of integer literals, while `Value`
+//! and the interpreter stay `f64`, so `x = 2^100; x + 1` is still rounded.
+
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    ops::{Add, Mul, Neg, Sub},
+};
+
+use crate::{parser::expressions::*, scanner::TokenType};
+
+/// Each limb holds nine decimal digits, which keeps printing trivial and
+/// leaves room for carries when two limbs are multiplied in a `u64`.
+const BASE: u64 = 1_000_000_000;
+
+/// The largest result `exact` will compute, in bits, so `2^(10^9)` gives up
+/// instead of running for hours.
+const MAX_BITS: u64 = 100_000;
+
+/// An arbitrary-precision integer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigInt {
+    negative: bool,
+    /// Base `BASE` digits, least significant first, with no trailing zeros.
+    /// Zero has no limbs.
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    /// Converts an integral `f64`, or returns `None` if it has a fractional
+    /// part or is too large to convert exactly.
+    pub fn from_f64(number: f64) -> Option<BigInt> {
+        if number.fract() != 0.0 || number.abs() >= u64::MAX as f64 {
+            return None;
+        }
+        let mut magnitude = number.abs() as u64;
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        Some(BigInt {
+            negative: number < 0.0,
+            limbs,
+        })
+    }
+
+    fn from_limbs(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+
+    /// Roughly the number of bits in the magnitude.
+    fn bits(&self) -> f64 {
+        match self.limbs.last() {
+            Some(most) => {
+                (self.limbs.len() - 1) as f64 * (BASE as f64).log2() + (*most as f64).log2() + 1.0
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Raises to a non-negative power by repeated squaring, or returns `None`
+    /// if the result would exceed `MAX_BITS`.
+    pub fn pow(&self, mut exponent: u64) -> Option<BigInt> {
+        // Zero and one stay small however large the exponent.
+        if self.bits() > 1.0 && self.bits() * exponent as f64 > MAX_BITS as f64 {
+            return None;
+        }
+        let mut result = BigInt::from_limbs(false, vec![1]);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent % 2 == 1 {
+                result = &result * &base;
+            }
+            exponent /= 2;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        Some(result)
+    }
+
+    /// Computes `n!`, or returns `None` if it would exceed `MAX_BITS`.
+    pub fn factorial(n: u64) -> Option<BigInt> {
+        let mut result = BigInt::from_limbs(false, vec![1]);
+        for k in 2..=n {
+            result = &result * &BigInt::from_f64(k as f64)?;
+            if result.bits() > MAX_BITS as f64 {
+                return None;
+            }
+        }
+        Some(result)
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_limbs(!self.negative, self.limbs)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_limbs(self.negative, add_limbs(&self.limbs, &other.limbs));
+        }
+        match compare_limbs(&self.limbs, &other.limbs) {
+            Ordering::Less => {
+                BigInt::from_limbs(other.negative, subtract_limbs(&other.limbs, &self.limbs))
+            }
+            _ => BigInt::from_limbs(self.negative, subtract_limbs(&self.limbs, &other.limbs)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other.clone()
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut limbs = vec![0u64; self.limbs.len() + other.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, b) in other.limbs.iter().enumerate() {
+                let product = limbs[i + j] + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = product % BASE;
+                carry = product / BASE;
+            }
+            limbs[i + other.limbs.len()] += carry;
+        }
+        let limbs = limbs.into_iter().map(|limb| limb as u32).collect();
+        BigInt::from_limbs(self.negative != other.negative, limbs)
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((most, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", most)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+fn compare_limbs(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        limbs.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    limbs.push(carry as u32);
+    limbs
+}
+
+/// Subtracts `b` from `a`, which must be at least as large.
+fn subtract_limbs(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, a) in a.iter().enumerate() {
+        let subtrahend = *b.get(i).unwrap_or(&0) as i64 + borrow;
+        let mut difference = *a as i64 - subtrahend;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        limbs.push(difference as u32);
+    }
+    limbs
+}
+
+/// Evaluates `expr` exactly if it is integer arithmetic on literals: `+`,
+/// `-`, `*`, `^` with a non-negative exponent, and `!`. Returns `None` for
+/// anything else, including variables and calls, so the caller can fall
+/// back to the `f64` result. This is the whole of the `bignum` feature:
+/// `Value` and the interpreter always work in `f64`.
+pub fn exact(expr: &Expr) -> Option<BigInt> {
+    match expr {
+        Expr::Literal(expr) if expr.value.kind == TokenType::Number => {
+            BigInt::from_f64(expr.value.literal?)
+        }
+        Expr::Grouping(expr) => exact(&expr.expression),
+        Expr::Unary(expr) => {
+            let right = exact(&expr.right)?;
+            match expr.operator.kind {
+                TokenType::Minus => Some(-right),
+                TokenType::Plus => Some(right),
+                TokenType::Bang if !right.negative => BigInt::factorial(to_u64(&right)?),
+                _ => None,
+            }
+        }
+        Expr::Binary(expr) => {
+            let left = exact(&expr.left)?;
+            let right = exact(&expr.right)?;
+            match expr.operator.kind {
+                TokenType::Plus => Some(&left + &right),
+                TokenType::Minus => Some(&left - &right),
+                TokenType::Star => Some(&left * &right),
+                TokenType::Caret if !right.negative => left.pow(to_u64(&right)?),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn to_u64(number: &BigInt) -> Option<u64> {
+    number.limbs.iter().rev().try_fold(0u64, |value, limb| {
+        value.checked_mul(BASE)?.checked_add(*limb as u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_text(source: &str) -> Option<String> {
+        exact(&crate::parse(source).unwrap()).map(|number| number.to_string())
+    }
+
+    #[test]
+    fn literal_integer_arithmetic_is_exact() {
+        // Expected values computed with Python's integers.
+        assert_eq!(exact_text("25!").unwrap(), "15511210043330985984000000");
+        assert_eq!(
+            exact_text("2^200").unwrap(),
+            "1606938044258990275541962092341162602522202993782792835301376"
+        );
+        assert_eq!(exact_text("2^53 - 2^54").unwrap(), "-9007199254740992");
+        assert_eq!(
+            exact_text("10^18 * 10^18 - 1").unwrap(),
+            "999999999999999999999999999999999999"
+        );
+        let factorial = exact_text("1000!").unwrap();
+        assert_eq!(factorial.len(), 2568);
+        assert!(factorial.starts_with("402387260077093773543702433923"));
+        let difference = exact_text("7^5000 - 3^9000").unwrap();
+        assert!(difference.starts_with('-'));
+        assert_eq!(difference.len(), 4296);
+    }
+
+    #[test]
+    fn anything_but_literal_integer_arithmetic_falls_back() {
+        for source in ["x", "x = 25!", "2^200 / 2", "2^-1", "1.5 * 2", "sqrt(4)"] {
+            assert_eq!(exact_text(source), None, "{}", source);
+        }
+        // Past `MAX_BITS` the result isn't computed at all.
+        assert_eq!(exact_text("2^100000000"), None);
+    }
+}
@@ -1,8 +1,8 @@
 use std::fmt::Display;
 
-use crate::scanner::Token;
+use crate::scanner::{Token, TokenType};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct CalculatorError {
     pub error: CalculatorErrorType,
     pub token: Option<Token>,
@@ -10,19 +10,98 @@ pub struct CalculatorError {
 
 impl Display for CalculatorError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.token {
-            Some(token) => write!(f, "(At '{}' in line {}) {:?}", token.lexeme, token.line, self.error),
-            None => write!(f, "{:?}", self.error),
+        if let Some(token) = &self.token {
+            write!(
+                f,
+                "(At '{}' in line {}:{}) ",
+                token.lexeme, token.line, token.column
+            )?;
         }
+        match &self.error {
+            CalculatorErrorType::UndefinedVariableOrFunction(name, Some(suggestion)) => write!(
+                f,
+                "UndefinedVariableOrFunction({:?}), did you mean '{}'?",
+                name, suggestion
+            ),
+            CalculatorErrorType::UndefinedVariableOrFunction(name, None) => {
+                write!(f, "UndefinedVariableOrFunction({:?})", name)
+            }
+            CalculatorErrorType::UnexpectedToken(TokenType::Dot) => write!(
+                f,
+                "UnexpectedToken(Dot), a '.' can only appear inside a number, like 1.5"
+            ),
+            CalculatorErrorType::ExpectedVariableName(name) => write!(
+                f,
+                "ExpectedVariableName({:?}), the variable argument must be a plain name like x",
+                name
+            ),
+            error => write!(f, "{:?}", error),
+        }
+    }
+}
+
+impl std::error::Error for CalculatorError {}
+
+impl CalculatorError {
+    /// Renders the error followed by the offending line of `source` with a
+    /// caret under the token, like:
+    ///
+    /// ```text
+    /// (At '$' in line 1:5) UnexpectedCharacter('$')
+    /// 1 + $
+    ///     ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let token = match &self.token {
+            Some(token) => token,
+            None => return self.to_string(),
+        };
+        let line = match source.lines().nth(token.line - 1) {
+            Some(line) => line,
+            None => return self.to_string(),
+        };
+        let padding = " ".repeat(token.column - 1);
+        let underline = "^".repeat(token.lexeme.chars().count().max(1));
+        format!("{}\n{}\n{}{}", self, line, padding, underline)
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum CalculatorErrorType {
     SyntaxError(String),
     AdditionalCodeAfterEnd,
     TooManyArguments,
     ExpectedExpression,
     FunctionArityMismatch(String, usize, usize),
-    UndefinedVariableOrFunction(String),
+    /// An unknown name, along with the closest known name if one is similar
+    /// enough to likely be what was meant.
+    UndefinedVariableOrFunction(String, Option<String>),
+    /// A variable such as `pi` was called like a function.
+    NotCallable(String),
+    /// The variable argument of the named special form, such as the `x` in
+    /// `deriv(x^2, x, 3)`, wasn't a plain name.
+    ExpectedVariableName(String),
+    /// `_n` was used before there were `n` results. `_0` never exists.
+    NoSuchResult(String),
+    InvalidNumberLiteral(String),
+    UnexpectedCharacter(char),
+    /// A token that can never appear where it was found, such as a `.` that
+    /// isn't part of a number.
+    UnexpectedToken(TokenType),
+    DomainError(String, f64),
+    ReservedName(String),
+    DivisionByZero,
+    /// An elementwise operation on vectors of these two lengths.
+    LengthMismatch(usize, usize),
+    /// An index into a vector of the given length that is past its end.
+    IndexOutOfBounds(f64, usize),
+    TypeError(String),
+    RecursionTooDeep(String),
+    /// The input nests parentheses or operators, or chains operators such as
+    /// `1 + 1 + ...`, deeper than the parser allows.
+    NestingTooDeep,
+    /// Evaluation was stopped by the named field of `Interpreter::limits`.
+    ResourceLimitExceeded(String),
+    /// The result was NaN or infinite while `Interpreter::strict_mode` is on.
+    NonFiniteResult(f64),
 }
@@ -0,0 +1,222 @@
+use crate::value::Value;
+
+/// Controls how results are printed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    /// The number of decimal places to round to, with trailing zeros
+    /// removed. `None` prints the shortest form that reads back exactly.
+    pub precision: Option<usize>,
+    pub base: Base,
+    pub notation: NotationMode,
+}
+
+/// The base integers are printed in. Non-integral results are always
+/// printed in decimal.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Base {
+    #[default]
+    Decimal,
+    Hexadecimal,
+    Binary,
+    Octal,
+}
+
+impl Base {
+    /// Looks up a base by its radix or name, such as `16` or `hex`.
+    pub fn from_name(name: &str) -> Option<Base> {
+        match name {
+            "10" | "dec" | "decimal" => Some(Base::Decimal),
+            "16" | "hex" | "hexadecimal" => Some(Base::Hexadecimal),
+            "2" | "bin" | "binary" => Some(Base::Binary),
+            "8" | "oct" | "octal" => Some(Base::Octal),
+            _ => None,
+        }
+    }
+}
+
+/// How decimal results are laid out. The precision, if set, is the number
+/// of decimal places in the mantissa for the exponent forms.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NotationMode {
+    /// Positional, switching to scientific notation for magnitudes of at
+    /// least `1e21` or below `1e-7`, where positional digits are unreadable.
+    #[default]
+    Auto,
+    /// Always positional, however many digits that takes.
+    Fixed,
+    /// Always a mantissa in `[1, 10)` and an exponent, like `1.23e4`.
+    Scientific,
+    /// Like scientific, but with the exponent a multiple of 3 and the
+    /// mantissa in `[1, 1000)`, like `12.3e3`.
+    Engineering,
+}
+
+impl NotationMode {
+    /// Looks up a notation by name, such as `eng` or `scientific`.
+    pub fn from_name(name: &str) -> Option<NotationMode> {
+        match name {
+            "auto" => Some(NotationMode::Auto),
+            "fixed" => Some(NotationMode::Fixed),
+            "sci" | "scientific" => Some(NotationMode::Scientific),
+            "eng" | "engineering" => Some(NotationMode::Engineering),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a number according to `opts`. Integral values never print a
+/// trailing `.0`.
+pub fn format_result(value: f64, opts: &FormatOptions) -> String {
+    // `-0` compares equal to `0` and is never what the user wants to see,
+    // e.g. from `0 * -1`.
+    let value = if value == 0.0 { 0.0 } else { value };
+    if opts.base != Base::Decimal && value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        let sign = if value < 0.0 { "-" } else { "" };
+        let magnitude = value.abs() as i64;
+        return match opts.base {
+            Base::Hexadecimal => format!("{sign}0x{magnitude:x}"),
+            Base::Binary => format!("{sign}0b{magnitude:b}"),
+            Base::Octal => format!("{sign}0o{magnitude:o}"),
+            Base::Decimal => unreachable!(),
+        };
+    }
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    let notation = match opts.notation {
+        NotationMode::Auto if value != 0.0 && (value.abs() >= 1e21 || value.abs() < 1e-7) => {
+            NotationMode::Scientific
+        }
+        notation => notation,
+    };
+    match notation {
+        NotationMode::Scientific => return scientific(value, opts.precision, 1),
+        NotationMode::Engineering => return scientific(value, opts.precision, 3),
+        NotationMode::Auto | NotationMode::Fixed => (),
+    }
+    match opts.precision {
+        Some(precision) => {
+            let formatted = format!("{:.*}", precision, value);
+            let formatted = if formatted.contains('.') {
+                formatted.trim_end_matches('0').trim_end_matches('.')
+            } else {
+                &formatted
+            };
+            // Rounding a small negative number can leave `-0`.
+            if formatted == "-0" {
+                "0".to_string()
+            } else {
+                formatted.to_string()
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Formats `value` as a mantissa and an exponent that is a multiple of
+/// `step`: 1 for scientific and 3 for engineering notation. Zero, including
+/// negative zero, is `0e0`.
+fn scientific(value: f64, precision: Option<usize>, step: i32) -> String {
+    if value == 0.0 {
+        return "0e0".to_string();
+    }
+    // Start from Rust's scientific form, whose digits are exact, and move
+    // the decimal point. With a precision the mantissa gets more digits the
+    // further the point moves. Rounding can carry into the exponent, e.g.
+    // 999.96 to 1.000e3, but then the rounded value is a power of ten whose
+    // digits after the first are all zeros, so the point is moved by the
+    // rounded exponent without formatting again.
+    let exponent =
+        |formatted: &str| -> i32 { formatted.split_once('e').unwrap().1.parse().unwrap() };
+    let shift = exponent(&format!("{:e}", value)).rem_euclid(step) as usize;
+    let formatted = match precision {
+        Some(precision) => format!("{:.*e}", precision + shift, value.abs()),
+        None => format!("{:e}", value.abs()),
+    };
+    let mantissa = formatted.split_once('e').unwrap().0;
+    let exponent = exponent(&formatted);
+    let shift = exponent.rem_euclid(step) as usize;
+    let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    while digits.len() < shift + 1 {
+        digits.push('0');
+    }
+    let (integer, fraction) = digits.split_at(shift + 1);
+    let fraction = fraction.trim_end_matches('0');
+    let sign = if value < 0.0 { "-" } else { "" };
+    let exponent = exponent - shift as i32;
+    if fraction.is_empty() {
+        format!("{sign}{integer}e{exponent}")
+    } else {
+        format!("{sign}{integer}.{fraction}e{exponent}")
+    }
+}
+
+/// Formats any value, using `format_result` for numbers and for each
+/// element of a vector.
+pub fn format_value(value: &Value, opts: &FormatOptions) -> String {
+    match value {
+        Value::Number(number) => format_result(*number, opts),
+        Value::Vector(vector) => {
+            let elements: Vec<_> = vector
+                .iter()
+                .map(|element| format_result(*element, opts))
+                .collect();
+            format!("[{}]", elements.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(precision: Option<usize>, base: Base, notation: NotationMode) -> FormatOptions {
+        FormatOptions {
+            precision,
+            base,
+            notation,
+        }
+    }
+
+    #[test]
+    fn negative_zero_prints_as_zero() {
+        let default = FormatOptions::default();
+        assert_eq!(format_result(-0.0, &default), "0");
+        for (opts, expected) in [
+            (with(Some(2), Base::Decimal, NotationMode::Auto), "0"),
+            (with(None, Base::Hexadecimal, NotationMode::Auto), "0x0"),
+            (with(None, Base::Decimal, NotationMode::Scientific), "0e0"),
+            (with(None, Base::Decimal, NotationMode::Engineering), "0e0"),
+        ] {
+            assert_eq!(format_result(-0.0, &opts), expected);
+        }
+        // Rounding away a small negative number doesn't leave a sign either.
+        assert_eq!(
+            format_result(-0.04, &with(Some(1), Base::Decimal, NotationMode::Auto)),
+            "0"
+        );
+        assert_eq!(
+            format_value(&Value::Vector(vec![-0.0, 1.0]), &default),
+            "[0, 1]"
+        );
+    }
+
+    #[test]
+    fn rounding_can_carry_into_the_exponent() {
+        let eng = with(Some(1), Base::Decimal, NotationMode::Engineering);
+        let sci = with(Some(1), Base::Decimal, NotationMode::Scientific);
+        for (value, opts, expected) in [
+            (999.96, &eng, "1e3"),
+            (99.96, &eng, "100e0"),
+            (-99.96, &eng, "-100e0"),
+            (9.96, &eng, "10e0"),
+            (9.96, &sci, "1e1"),
+            (0.09996, &sci, "1e-1"),
+            (99.94, &eng, "99.9e0"),
+            (123456.0, &eng, "123.5e3"),
+        ] {
+            assert_eq!(format_result(value, opts), expected, "{}", value);
+        }
+    }
+}
@@ -1,125 +1,1123 @@
 use crate::{
     errors::{CalculatorError, CalculatorErrorType},
     parser::expressions::*,
-    scanner::TokenType,
+    scanner::{Token, TokenType},
+    scope::Scope,
+    value::Value,
 };
-use std::{collections::HashMap, f64::consts};
+use std::{
+    collections::{HashMap, HashSet},
+    f64::consts,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+// Functions are reference counted and thread-safe so a configured
+// interpreter can be cloned cheaply and shared through an `Environment`.
+pub type SingleFunction = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+pub type DoubleFunction = Arc<dyn Fn(f64, f64) -> f64 + Send + Sync>;
+pub type TripleFunction = Arc<dyn Fn(f64, f64, f64) -> f64 + Send + Sync>;
+/// A function taking one or more arguments.
+pub type VariadicFunction = Arc<dyn Fn(&[f64]) -> f64 + Send + Sync>;
+/// A function that needs the interpreter itself, such as `random` reading
+/// the RNG state. Being reference counted also lets it be called while the
+/// interpreter is mutably borrowed.
+pub type StatefulFunction = Arc<dyn Fn(&mut Interpreter, &[f64]) -> f64 + Send + Sync>;
+
+/// The unit used for the arguments of `sin`, `cos` and `tan` and the
+/// results of `asin`, `acos`, `atan` and `atan2`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    fn angle_to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+        }
+    }
+
+    fn radians_to_angle(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_degrees(),
+        }
+    }
+}
+
+/// How a built-in function relates to the angle mode, which decides where
+/// the interpreter converts between degrees and radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleUsage {
+    /// Neither takes nor returns an angle, like `sqrt`.
+    #[default]
+    Plain,
+    /// Takes angles as its arguments, like `sin`.
+    Input,
+    /// Returns an angle, like `atan2`.
+    Output,
+}
+
+/// How many arguments a function accepts, as reported by
+/// `Interpreter::arity`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arity {
+    Single,
+    Double,
+    Triple,
+    /// One or more arguments.
+    Variadic,
+    /// A user-defined function taking some other fixed number of arguments.
+    Fixed(usize),
+    /// Several fixed arities, such as `round(x)` and `round(x, digits)`.
+    Overloaded(Vec<Arity>),
+}
 
+impl Arity {
+    fn from_count(count: usize) -> Arity {
+        match count {
+            1 => Arity::Single,
+            2 => Arity::Double,
+            3 => Arity::Triple,
+            count => Arity::Fixed(count),
+        }
+    }
+}
+
+/// A function defined by the user, such as `f(x) = x^2 + 1`.
+#[derive(Clone)]
+pub struct UserFunction {
+    pub parameters: Vec<String>,
+    pub body: Arc<Expr>,
+}
+
+/// How deeply user-defined functions may call each other before evaluation
+/// is aborted, so runaway recursion can't overflow the stack.
+const MAX_CALL_DEPTH: usize = 256;
+
+/// Bounds on the work a single `interpret` call may do, for evaluating
+/// untrusted input. Exceeding `max_nodes` or `max_steps` fails with
+/// `ResourceLimitExceeded`, and `max_call_depth` with `RecursionTooDeep`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// The most nodes the expression tree may have, checked before any of it
+    /// is evaluated.
+    pub max_nodes: Option<usize>,
+    /// The most nodes evaluated, counting a function body or `sum` term
+    /// again each time it runs.
+    pub max_steps: Option<u64>,
+    /// How deeply user-defined functions may call each other.
+    pub max_call_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_nodes: None,
+            max_steps: None,
+            max_call_depth: MAX_CALL_DEPTH,
+        }
+    }
+}
+
+/// Evaluates expressions against a set of variables and functions.
+///
+/// Prefer registering functions through `add_single_function` and
+/// `add_double_function` over inserting into the maps directly, since the
+/// methods also reserve the name against reassignment. The built-in maps
+/// are shared between clones until one of them registers something new.
+#[derive(Clone)]
 pub struct Interpreter {
-    pub variables: HashMap<String, f64>,
-    pub single_functions: HashMap<String, fn(f64) -> f64>,
-    pub double_functions: HashMap<String, fn(f64, f64) -> f64>,
+    pub scope: Scope,
+    pub single_functions: Arc<HashMap<String, SingleFunction>>,
+    pub double_functions: Arc<HashMap<String, DoubleFunction>>,
+    pub triple_functions: Arc<HashMap<String, TripleFunction>>,
+    pub variadic_functions: Arc<HashMap<String, VariadicFunction>>,
+    /// Each function is stored with the argument counts it accepts.
+    pub stateful_functions: Arc<HashMap<String, (Vec<usize>, StatefulFunction)>>,
+    pub user_functions: HashMap<String, UserFunction>,
+    /// Every result passed to `record_result`, which `_1`, `_2` and so on
+    /// refer to.
+    pub results: Vec<Value>,
+    pub angle_mode: AngleMode,
+    /// Functions missing from this map are `AngleUsage::Plain`.
+    pub angle_usage: Arc<HashMap<String, AngleUsage>>,
+    /// Lets assignments and function definitions replace built-in names,
+    /// such as `pi = 3`, instead of failing with `ReservedName`.
+    pub allow_override: bool,
+    /// Rejects NaN and infinite final results, for callers who don't want
+    /// IEEE semantics leaking out of a calculation.
+    pub strict_mode: bool,
+    /// The relative tolerance `==` and the other comparisons allow, see
+    /// `approx_eq`. Set it to 0 to compare exactly.
+    pub epsilon: f64,
+    pub limits: Limits,
+    /// One-line descriptions of built-ins, shown by the REPL's `:help`.
+    pub descriptions: Arc<HashMap<String, String>>,
+    /// Names registered through the `add_*` methods, which cannot be reassigned.
+    builtins: Arc<HashSet<String>>,
+    call_depth: usize,
+    /// Nodes evaluated so far by the current `interpret` call.
+    steps: u64,
+    rng_state: u64,
 }
 
+/// The most terms `sum(i, from, to, body)` and `prod` will evaluate, so a
+/// range like `1` to `1e300` fails instead of running for years.
+const MAX_SERIES_TERMS: i64 = 10_000_000;
+
+/// The default step for `deriv`, which balances truncation error against
+/// rounding error for arguments near 1.
+const DERIVATIVE_STEP: f64 = 1e-5;
+
+/// The default `Interpreter::epsilon`. About 4500 units in the last place,
+/// so it absorbs the rounding of long calculations while still telling
+/// apart numbers that differ in the twelfth significant digit.
+pub const DEFAULT_EPSILON: f64 = 1e-12;
+
 const PHI: f64 = 1.618033988749895;
 
-impl Visitor for Interpreter {
-    fn visit_binary_expr(&mut self, expr: &Binary) -> Result<f64, CalculatorError> {
-        let left = self.interpret(&*expr.left)?;
-        let right = self.interpret(&*expr.right)?;
-
-        Ok(match expr.operator.kind {
-            TokenType::Plus => left + right,
-            TokenType::Minus => left - right,
-            TokenType::Star => left * right,
-            TokenType::Slash => left / right,
-            TokenType::Caret => left.powf(right),
-            _ => todo!(),
-        })
+/// Computes `n!` for a non-negative integer `n`, overflowing to infinity
+/// past `170!`.
+fn factorial(n: f64) -> f64 {
+    let mut result: f64 = 1.0;
+    let mut i = 2.0;
+    while i <= n && result.is_finite() {
+        result *= i;
+        i += 1.0;
+    }
+    result
+}
+
+/// C's `fmod`: the truncated remainder, which takes the sign of `x`.
+fn fmod(x: f64, y: f64) -> f64 {
+    x % y
+}
+
+/// The floored modulo, which takes the sign of `y`, so `mod(-7, 3)` is `2`
+/// where `fmod` and `%` give `-1`.
+fn floored_mod(x: f64, y: f64) -> f64 {
+    let remainder = x % y;
+    if remainder != 0.0 && (remainder < 0.0) != (y < 0.0) {
+        remainder + y
+    } else {
+        remainder
+    }
+}
+
+/// IEEE 754 remainder: `x - n * y` where `n` is `x / y` rounded to the
+/// nearest integer, ties to even.
+fn remainder(x: f64, y: f64) -> f64 {
+    x - (x / y).round_ties_even() * y
+}
+
+/// The `n`th root of `x`. Odd integer roots of negative numbers are real,
+/// unlike with `powf`, while even roots of negative numbers are NaN.
+fn root(x: f64, n: f64) -> f64 {
+    let odd = n.fract() == 0.0 && n % 2.0 != 0.0;
+    let result = if x < 0.0 && odd {
+        -(-x).powf(1.0 / n)
+    } else {
+        x.powf(1.0 / n)
+    };
+    // `powf(1.0 / n)` is inexact, so snap to an integer root if there is one.
+    if result.round().powf(n) == x {
+        result.round()
+    } else {
+        result
+    }
+}
+
+/// Rounds `x` to `digits` decimal places, where negative `digits` round to
+/// tens, hundreds and so on.
+fn round_to(x: f64, digits: f64) -> f64 {
+    let factor = 10f64.powf(digits.trunc());
+    if factor == 0.0 {
+        // Rounding to a place beyond any representable magnitude.
+        return 0.0;
+    }
+    let scaled = x * factor;
+    if !scaled.is_finite() {
+        // `x` has no digits that far past the decimal point.
+        return x;
+    }
+    scaled.round() / factor
+}
+
+/// The sine of `x` degrees. The angle is reduced before converting to
+/// radians, and the multiples of 30 and 90 degrees with rational sines are
+/// exact, so `sind(30)` is `0.5` rather than `0.49999999999999994`.
+fn sind(x: f64) -> f64 {
+    let x = x.rem_euclid(360.0);
+    match x {
+        0.0 | 180.0 => 0.0,
+        30.0 | 150.0 => 0.5,
+        90.0 => 1.0,
+        210.0 | 330.0 => -0.5,
+        270.0 => -1.0,
+        _ => x.to_radians().sin(),
+    }
+}
+
+/// The cosine of `x` degrees, exact where `sind` is.
+fn cosd(x: f64) -> f64 {
+    sind(x.rem_euclid(360.0) + 90.0)
+}
+
+/// The tangent of `x` degrees, exact at multiples of 45 degrees and NaN at
+/// odd multiples of 90, where it is undefined.
+fn tand(x: f64) -> f64 {
+    let x = x.rem_euclid(180.0);
+    match x {
+        0.0 => 0.0,
+        45.0 => 1.0,
+        90.0 => f64::NAN,
+        135.0 => -1.0,
+        _ => x.to_radians().tan(),
+    }
+}
+
+/// Converts a result in radians to degrees, snapping to the nearest whole
+/// degree when only rounding error separates them, so `asind(0.5)` is `30`.
+fn to_whole_degrees(radians: f64) -> f64 {
+    let degrees = radians.to_degrees();
+    if (degrees - degrees.round()).abs() <= 1e-12 * degrees.abs() {
+        degrees.round()
+    } else {
+        degrees
+    }
+}
+
+/// Converts the arguments of an integer function to `i64`, or `None` if
+/// either is negative, fractional or too large.
+fn integer_arguments(x: f64, y: f64) -> Option<(i64, i64)> {
+    let convert = |n: f64| {
+        if n >= 0.0 && n.fract() == 0.0 && n < i64::MAX as f64 {
+            Some(n as i64)
+        } else {
+            None
+        }
+    };
+    Some((convert(x)?, convert(y)?))
+}
+
+fn gcd_i64(mut a: i64, mut b: i64) -> i64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The greatest common divisor, or NaN for invalid arguments.
+fn gcd(x: f64, y: f64) -> f64 {
+    match integer_arguments(x, y) {
+        Some((a, b)) => gcd_i64(a, b) as f64,
+        None => f64::NAN,
+    }
+}
+
+/// The least common multiple, or NaN for invalid arguments and infinity if
+/// the result overflows an `i64`.
+fn lcm(x: f64, y: f64) -> f64 {
+    match integer_arguments(x, y) {
+        Some((0, _)) | Some((_, 0)) => 0.0,
+        Some((a, b)) => match (a / gcd_i64(a, b)).checked_mul(b) {
+            Some(result) => result as f64,
+            None => f64::INFINITY,
+        },
+        None => f64::NAN,
+    }
+}
+
+/// The number of ways to choose `r` of `n` items, ignoring order. NaN for
+/// invalid arguments or `r > n`, and infinity if the result overflows.
+fn ncr(n: f64, r: f64) -> f64 {
+    match integer_arguments(n, r) {
+        Some((n, r)) if r <= n => {
+            let (n, r) = (n as u128, r.min(n - r) as u128);
+            let mut result: u128 = 1;
+            for i in 0..r {
+                // Each partial product is itself a binomial coefficient, so
+                // the division is always exact.
+                result = match result.checked_mul(n - i) {
+                    Some(product) => product / (i + 1),
+                    None => return f64::INFINITY,
+                };
+            }
+            result as f64
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// The number of ways to arrange `r` of `n` items in order. NaN for invalid
+/// arguments or `r > n`, and infinity if the result overflows.
+fn npr(n: f64, r: f64) -> f64 {
+    match integer_arguments(n, r) {
+        Some((n, r)) if r <= n => {
+            let mut result: u128 = 1;
+            for k in (n - r + 1)..=n {
+                result = match result.checked_mul(k as u128) {
+                    Some(product) => product,
+                    None => return f64::INFINITY,
+                };
+            }
+            result as f64
+        }
+        _ => f64::NAN,
+    }
+}
+
+/// The edit distance between `a` and `b`: how many single character
+/// insertions, deletions, substitutions or swaps of adjacent characters
+/// turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // `distances[i][j]` is the distance between the first `i` characters of
+    // `a` and the first `j` of `b`.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// The `n` in a name of the form `_n`, which refers to the `n`th recorded
+/// result.
+fn result_index(name: &str) -> Option<usize> {
+    let digits = name.strip_prefix('_')?;
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    // Too many digits to be an index can only mean a result that doesn't
+    // exist.
+    Some(digits.parse().unwrap_or(usize::MAX))
+}
+
+/// Writes `number` so that evaluating the text gives it back. `inf` and `NaN`
+/// aren't literals, so they're written as expressions that overflow.
+fn number_source(number: f64) -> String {
+    if number.is_nan() {
+        "(1e309 - 1e309)".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "1e309" } else { "-1e309" }.to_string()
+    } else {
+        number.to_string()
+    }
+}
+
+/// Seeds the random number generator from the clock so each session differs
+/// unless `seed` is called. Browsers have no clock `std` can read, and
+/// reading it would panic, so WebAssembly builds start from a fixed seed.
+fn clock_seed() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    #[cfg(target_arch = "wasm32")]
+    return 0;
+}
+
+/// Limits `x` to the range `lo..=hi`, or NaN if the range is empty.
+fn clamp(x: f64, lo: f64, hi: f64) -> f64 {
+    if lo > hi {
+        return f64::NAN;
+    }
+    x.clamp(lo, hi)
+}
+
+fn mean(args: &[f64]) -> f64 {
+    args.iter().sum::<f64>() / args.len() as f64
+}
+
+fn median(args: &[f64]) -> f64 {
+    let mut sorted = args.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// The population standard deviation.
+fn stddev(args: &[f64]) -> f64 {
+    let mean = mean(args);
+    let variance = args.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / args.len() as f64;
+    variance.sqrt()
+}
+
+/// Whether `a` and `b` differ by at most `epsilon` relative to the larger
+/// of the two, which is exact equality when `epsilon` is 0. Being relative,
+/// nothing but zero is ever equal to zero.
+pub fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
+    // Without the finiteness check every number would be within
+    // `epsilon * inf` of infinity.
+    a == b || (a.is_finite() && b.is_finite() && (a - b).abs() <= epsilon * a.abs().max(b.abs()))
+}
+
+/// Applies a binary operator to already evaluated operands, comparing
+/// numbers with `approx_eq`.
+fn binary_operation(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    epsilon: f64,
+) -> Result<Value, CalculatorError> {
+    match (left, right) {
+        (Value::Bool(left), Value::Bool(right)) => match operator.kind {
+            TokenType::EqualEqual => return Ok((left == right).into()),
+            TokenType::BangEqual => return Ok((left != right).into()),
+            _ => (),
+        },
+        (Value::Vector(_), _) | (_, Value::Vector(_)) => {
+            return vector_operation(operator, left, right, epsilon)
+        }
+        _ => (),
+    }
+
+    number_operation(
+        operator,
+        left.to_number(operator)?,
+        right.to_number(operator)?,
+        epsilon,
+    )
+}
+
+/// Applies an arithmetic operator elementwise when either operand is a
+/// vector, pairing a number with every element, so `[1, 2] * 2` is
+/// `[2, 4]`. Two vectors must be the same length. Vectors can be compared
+/// for equality with each other but not ordered.
+fn vector_operation(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    epsilon: f64,
+) -> Result<Value, CalculatorError> {
+    if let (Value::Vector(left), Value::Vector(right)) = (left, right) {
+        let equal = left.len() == right.len()
+            && left
+                .iter()
+                .zip(right)
+                .all(|(left, right)| approx_eq(*left, *right, epsilon));
+        match operator.kind {
+            TokenType::EqualEqual => return Ok(equal.into()),
+            TokenType::BangEqual => return Ok((!equal).into()),
+            _ => (),
+        }
+    }
+    if !matches!(
+        operator.kind,
+        TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::Modulo
+            | TokenType::Caret
+    ) {
+        return Err(CalculatorError {
+            error: CalculatorErrorType::TypeError(format!(
+                "'{}' can't be applied to vectors.",
+                operator.lexeme
+            )),
+            token: Some(operator.clone()),
+        });
+    }
+    let pairs: Vec<(f64, f64)> = match (left, right) {
+        (Value::Vector(left), Value::Vector(right)) => {
+            if left.len() != right.len() {
+                return Err(CalculatorError {
+                    error: CalculatorErrorType::LengthMismatch(left.len(), right.len()),
+                    token: Some(operator.clone()),
+                });
+            }
+            left.iter().copied().zip(right.iter().copied()).collect()
+        }
+        (Value::Vector(left), right) => {
+            let right = right.to_number(operator)?;
+            left.iter().map(|left| (*left, right)).collect()
+        }
+        (left, right) => {
+            let left = left.to_number(operator)?;
+            right
+                .to_vector(operator)?
+                .iter()
+                .map(|right| (left, *right))
+                .collect()
+        }
+    };
+    pairs
+        .into_iter()
+        .map(|(left, right)| number_operation(operator, left, right, epsilon)?.to_number(operator))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Value::from)
+}
+
+/// Applies a binary operator to two numbers.
+fn number_operation(
+    operator: &Token,
+    left: f64,
+    right: f64,
+    epsilon: f64,
+) -> Result<Value, CalculatorError> {
+    if matches!(operator.kind, TokenType::Slash | TokenType::Modulo) && right == 0.0 {
+        return Err(CalculatorError {
+            error: CalculatorErrorType::DivisionByZero,
+            token: Some(operator.clone()),
+        });
+    }
+
+    Ok(match operator.kind {
+        TokenType::Plus => (left + right).into(),
+        TokenType::Minus => (left - right).into(),
+        TokenType::Star => (left * right).into(),
+        TokenType::Slash => (left / right).into(),
+        // Truncated remainder like C's `%`, so the result takes the sign
+        // of the left operand: `-7 % 3` is `-1`.
+        TokenType::Modulo => (left % right).into(),
+        TokenType::Caret => left.powf(right).into(),
+        // Numbers within epsilon of each other are equal, so
+        // `0.1 + 0.2 == 0.3`, and the orderings agree with that.
+        TokenType::Less => (left < right && !approx_eq(left, right, epsilon)).into(),
+        TokenType::Greater => (left > right && !approx_eq(left, right, epsilon)).into(),
+        TokenType::LessEqual => (left <= right || approx_eq(left, right, epsilon)).into(),
+        TokenType::GreaterEqual => (left >= right || approx_eq(left, right, epsilon)).into(),
+        TokenType::EqualEqual => approx_eq(left, right, epsilon).into(),
+        TokenType::BangEqual => (!approx_eq(left, right, epsilon)).into(),
+        // The parser never builds these, but a tree read from JSON might.
+        _ => {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::TypeError(format!(
+                    "'{}' is not a binary operator.",
+                    operator.lexeme
+                )),
+                token: Some(operator.clone()),
+            })
+        }
+    })
+}
+
+impl Interpreter {
+    fn evaluate_binary(&mut self, expr: &Binary) -> Result<Value, CalculatorError> {
+        let left = self.evaluate(&expr.left)?;
+        let right = self.evaluate(&expr.right)?;
+        binary_operation(&expr.operator, &left, &right, self.epsilon)
+    }
+
+    fn evaluate_logical(&mut self, expr: &Logical) -> Result<Value, CalculatorError> {
+        let left = self.evaluate(&expr.left)?.to_bool(&expr.operator)?;
+        // The right operand is skipped entirely when the left decides the
+        // result, so `x != 0 and 1 / x > 2` never divides by zero.
+        let decided = match expr.operator.kind {
+            TokenType::And => !left,
+            _ => left,
+        };
+        if decided {
+            return Ok(left.into());
+        }
+        Ok(self.evaluate(&expr.right)?.to_bool(&expr.operator)?.into())
+    }
+
+    /// The condition may be a boolean or a number, which is true when
+    /// nonzero.
+    fn evaluate_conditional(&mut self, expr: &Conditional) -> Result<Value, CalculatorError> {
+        if self.evaluate(&expr.condition)?.to_bool(&expr.question)? {
+            self.evaluate(&expr.then)
+        } else {
+            self.evaluate(&expr.otherwise)
+        }
+    }
+
+    fn evaluate_comparison(&mut self, expr: &Comparison) -> Result<Value, CalculatorError> {
+        let mut left = self.evaluate(&expr.operands[0])?;
+        for (operator, operand) in expr.operators.iter().zip(&expr.operands[1..]) {
+            let right = self.evaluate(operand)?;
+            if binary_operation(operator, &left, &right, self.epsilon)? == Value::Bool(false) {
+                return Ok(false.into());
+            }
+            left = right;
+        }
+        Ok(true.into())
     }
 
-    fn visit_grouping_expr(&mut self, expr: &Grouping) -> Result<f64, CalculatorError> {
-        self.interpret(&*expr.expression)
+    fn evaluate_grouping(&mut self, expr: &Grouping) -> Result<Value, CalculatorError> {
+        self.evaluate(&expr.expression)
     }
 
-    fn visit_literal_expr(&mut self, expr: &Literal) -> Result<f64, CalculatorError> {
-        Ok(expr.value.literal.unwrap_or(0.0))
+    /// Vectors hold numbers only, so `[[1, 2]]` is a type error.
+    fn evaluate_list(&mut self, expr: &List) -> Result<Value, CalculatorError> {
+        expr.elements
+            .iter()
+            .map(|element| self.evaluate(element)?.to_number(&expr.bracket))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::from)
+    }
+
+    /// Indices start at 0 and must be whole numbers. A negative index is out
+    /// of bounds rather than counting from the end.
+    fn evaluate_index(&mut self, expr: &Index) -> Result<Value, CalculatorError> {
+        let object = self.evaluate(&expr.object)?;
+        let vector = object.to_vector(&expr.bracket)?;
+        let index = self.evaluate(&expr.index)?.to_number(&expr.bracket)?;
+        if index.fract() != 0.0 || index.is_nan() {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::DomainError(expr.bracket.lexeme.to_string(), index),
+                token: Some(expr.bracket.clone()),
+            });
+        }
+        if index < 0.0 || index >= vector.len() as f64 {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::IndexOutOfBounds(index, vector.len()),
+                token: Some(expr.bracket.clone()),
+            });
+        }
+        Ok(vector[index as usize].into())
     }
 
-    fn visit_unary_expr(&mut self, expr: &Unary) -> Result<f64, CalculatorError> {
-        let right = self.interpret(&*expr.right)?;
+    fn evaluate_literal(&mut self, expr: &Literal) -> Result<Value, CalculatorError> {
+        Ok(match expr.value.kind {
+            TokenType::True => true.into(),
+            TokenType::False => false.into(),
+            _ => expr.value.literal.unwrap_or(0.0).into(),
+        })
+    }
+
+    fn evaluate_unary(&mut self, expr: &Unary) -> Result<Value, CalculatorError> {
+        if expr.operator.kind == TokenType::Not {
+            let right = self.evaluate(&expr.right)?.to_bool(&expr.operator)?;
+            return Ok((!right).into());
+        }
+
+        let right = self.evaluate(&expr.right)?;
+        // Only the sign operators apply to vectors elementwise.
+        if let Value::Vector(vector) = &right {
+            match expr.operator.kind {
+                TokenType::Minus => {
+                    return Ok(vector.iter().map(|x| -x).collect::<Vec<_>>().into())
+                }
+                TokenType::Plus => return Ok(right),
+                _ => (),
+            }
+        }
+        let right = right.to_number(&expr.operator)?;
 
         match expr.operator.kind {
-            TokenType::Minus => Ok(-right),
-            TokenType::Plus => Ok(right),
-            _ => todo!(),
+            TokenType::Minus => Ok((-right).into()),
+            TokenType::Plus => Ok(right.into()),
+            TokenType::Bar => Ok(right.abs().into()),
+            TokenType::Modulo => Ok((right / 100.0).into()),
+            TokenType::Bang => {
+                if right < 0.0 || right.fract() != 0.0 {
+                    return Err(CalculatorError {
+                        error: CalculatorErrorType::DomainError(
+                            expr.operator.lexeme.to_string(),
+                            right,
+                        ),
+                        token: Some(expr.operator.clone()),
+                    });
+                }
+                Ok(factorial(right).into())
+            }
+            _ => Err(CalculatorError {
+                error: CalculatorErrorType::TypeError(format!(
+                    "'{}' is not a unary operator.",
+                    expr.operator.lexeme
+                )),
+                token: Some(expr.operator.clone()),
+            }),
         }
     }
 
-    fn visit_call_expr(&mut self, expr: &Call) -> Result<f64, CalculatorError> {
-        let mut arguments = expr
+    fn evaluate_call(&mut self, expr: &Call) -> Result<Value, CalculatorError> {
+        if let Some(result) = self.special_form(expr) {
+            return result;
+        }
+
+        let arguments = expr
             .arguments
             .iter()
-            .map(|arg| self.interpret(&**arg))
-            .collect::<Vec<_>>();
+            .map(|arg| {
+                self.evaluate(arg)
+                    .and_then(|value| value.to_number(&expr.callee))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         let name = &expr.callee.lexeme;
 
-        if let Some(function) = self.single_functions.get(name) {
-            if arguments.len() != 1 {
+        if let Some(function) = self.user_functions.get(name) {
+            let parameters = function.parameters.clone();
+            let body = Arc::clone(&function.body);
+            if parameters.len() != arguments.len() {
                 return Err(CalculatorError {
                     error: CalculatorErrorType::FunctionArityMismatch(
                         name.to_string(),
                         arguments.len(),
-                        1,
+                        parameters.len(),
                     ),
-                    token: None,
+                    token: Some(expr.callee.clone()),
                 });
             }
-            let argument = arguments.remove(0)?;
-            Ok(function(argument))
-        } else if let Some(function) = self.double_functions.get(name) {
-            if arguments.len() != 2 {
+            return self.call_user_function(expr, &parameters, &body, arguments);
+        }
+
+        if let Some((arities, function)) = self.stateful_functions.get(name) {
+            if !arities.contains(&arguments.len()) {
                 return Err(CalculatorError {
                     error: CalculatorErrorType::FunctionArityMismatch(
                         name.to_string(),
                         arguments.len(),
-                        2,
+                        arities[0],
                     ),
-                    token: None,
+                    token: Some(expr.callee.clone()),
                 });
             }
-            let left = arguments.remove(0)?;
-            let right = arguments.remove(0)?;
-            Ok(function(left, right))
-        } else {
-            Err(CalculatorError {
-                error: CalculatorErrorType::UndefinedVariableOrFunction(name.to_string()),
-                token: None,
-            })
+            let function = Arc::clone(function);
+            let result = function(self, &arguments);
+            return self.check_domain(expr, &arguments, result);
         }
+
+        let usage = self.angle_usage.get(name).copied().unwrap_or_default();
+        let inputs: Vec<_> = match usage {
+            AngleUsage::Input => arguments
+                .iter()
+                .map(|argument| self.angle_mode.angle_to_radians(*argument))
+                .collect(),
+            _ => arguments.clone(),
+        };
+
+        // A name may be registered with several arities, e.g. the two argument
+        // and variadic `max`, so the exact arity is tried first.
+        let result = match (
+            inputs.as_slice(),
+            self.single_functions.get(name),
+            self.double_functions.get(name),
+            self.triple_functions.get(name),
+            self.variadic_functions.get(name),
+        ) {
+            ([argument], Some(function), _, _, _) => function(*argument),
+            ([left, right], _, Some(function), _, _) => function(*left, *right),
+            ([first, second, third], _, _, Some(function), _) => function(*first, *second, *third),
+            ([_, ..], _, _, _, Some(function)) => function(&inputs),
+            (_, single, double, triple, variadic) => {
+                let expected = if single.is_some() || variadic.is_some() {
+                    1
+                } else if double.is_some() {
+                    2
+                } else if triple.is_some() {
+                    3
+                } else if self.scope.get(name).is_some() {
+                    return Err(CalculatorError {
+                        error: CalculatorErrorType::NotCallable(name.to_string()),
+                        token: Some(expr.callee.clone()),
+                    });
+                } else {
+                    return Err(self.undefined(&expr.callee));
+                };
+                return Err(CalculatorError {
+                    error: CalculatorErrorType::FunctionArityMismatch(
+                        name.to_string(),
+                        arguments.len(),
+                        expected,
+                    ),
+                    token: Some(expr.callee.clone()),
+                });
+            }
+        };
+        let result = match usage {
+            AngleUsage::Output => self.angle_mode.radians_to_angle(result),
+            _ => result,
+        };
+
+        self.check_domain(expr, &arguments, result)
     }
 
-    fn visit_variable_expr(&mut self, expr: &Variable) -> Result<f64, CalculatorError> {
+    fn evaluate_variable(&mut self, expr: &Variable) -> Result<Value, CalculatorError> {
         let name = &expr.name.lexeme;
-        if let Some(value) = self.variables.get(name) {
-            Ok(*value)
+        if let Some(value) = self.scope.get(name) {
+            Ok(value)
+        } else if let Some(index) = result_index(name) {
+            match index
+                .checked_sub(1)
+                .and_then(|index| self.results.get(index))
+            {
+                Some(value) => Ok(value.clone()),
+                None => Err(CalculatorError {
+                    error: CalculatorErrorType::NoSuchResult(name.to_string()),
+                    token: Some(expr.name.clone()),
+                }),
+            }
         } else {
-            Err(CalculatorError {
-                error: CalculatorErrorType::UndefinedVariableOrFunction(name.to_string()),
-                token: None,
-            })
+            Err(self.undefined(&expr.name))
+        }
+    }
+
+    /// An `UndefinedVariableOrFunction` error for `name`, suggesting the
+    /// closest known name if it is only a typo or two away.
+    fn undefined(&self, name: &Token) -> CalculatorError {
+        let mut candidates: Vec<_> = self
+            .scope
+            .names()
+            .into_iter()
+            .chain(self.builtins.iter())
+            .chain(self.user_functions.keys())
+            .collect();
+        // Sorted so ties are broken the same way every time.
+        candidates.sort();
+        // One edit per three characters, so short names like `foo` aren't
+        // matched with unrelated ones like `cos`. Single character names
+        // never get a suggestion, and neither does a function used as a
+        // variable, where the only sensible suggestion would be the name
+        // itself.
+        let length = name.lexeme.chars().count();
+        let max_distance = if length < 2 || self.arity(&name.lexeme).is_some() {
+            0
+        } else {
+            (length / 3).clamp(1, 2)
+        };
+        let suggestion = candidates
+            .into_iter()
+            .map(|candidate| (edit_distance(&name.lexeme, candidate), candidate))
+            .filter(|(distance, _)| (1..=max_distance).contains(distance))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate.to_string());
+        CalculatorError {
+            error: CalculatorErrorType::UndefinedVariableOrFunction(
+                name.lexeme.to_string(),
+                suggestion,
+            ),
+            token: Some(name.clone()),
         }
     }
+
+    fn evaluate_assignment(&mut self, expr: &Assignment) -> Result<Value, CalculatorError> {
+        let name = &expr.name.lexeme;
+        if self.builtins.contains(name) && !self.allow_override {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::ReservedName(name.to_string()),
+                token: Some(expr.name.clone()),
+            });
+        }
+        let value = self.evaluate(&expr.value)?;
+        self.scope.define(name, value.clone());
+        Ok(value)
+    }
+
+    fn evaluate_function_definition(
+        &mut self,
+        expr: &FunctionDefinition,
+    ) -> Result<Value, CalculatorError> {
+        let name = &expr.name.lexeme;
+        if self.builtins.contains(name) && !self.allow_override {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::ReservedName(name.to_string()),
+                token: Some(expr.name.clone()),
+            });
+        }
+        self.user_functions.insert(
+            name.to_string(),
+            UserFunction {
+                parameters: expr.parameters.iter().map(|p| p.lexeme.clone()).collect(),
+                body: Arc::clone(&expr.body),
+            },
+        );
+        Ok(Value::Unit)
+    }
+}
+
+/// Builds an `Interpreter` with only some groups of built-ins. See
+/// `Interpreter::builder`.
+pub struct InterpreterBuilder {
+    interpreter: Interpreter,
+}
+
+impl InterpreterBuilder {
+    /// `pi`, `e`, `tau`, `phi` and their symbols.
+    pub fn with_constants(mut self) -> InterpreterBuilder {
+        self.interpreter.add_constants();
+        self
+    }
+
+    /// Trigonometric and hyperbolic functions, `atan2` and `hypot`.
+    pub fn with_trig(mut self) -> InterpreterBuilder {
+        self.interpreter.add_trig();
+        self
+    }
+
+    /// Roots, exponentials, logarithms, rounding and remainders.
+    pub fn with_elementary(mut self) -> InterpreterBuilder {
+        self.interpreter.add_elementary();
+        self
+    }
+
+    /// `gcd`, `lcm`, `ncr` and `npr`.
+    pub fn with_combinatorics(mut self) -> InterpreterBuilder {
+        self.interpreter.add_combinatorics();
+        self
+    }
+
+    /// `sum`, `prod`, `mean`, `max`, `min`, `median` and `stddev`.
+    pub fn with_statistics(mut self) -> InterpreterBuilder {
+        self.interpreter.add_statistics();
+        self
+    }
+
+    /// The `deriv` special form.
+    pub fn with_calculus(mut self) -> InterpreterBuilder {
+        self.interpreter.add_calculus();
+        self
+    }
+
+    /// `dot` and `len`.
+    pub fn with_vectors(mut self) -> InterpreterBuilder {
+        self.interpreter.add_vectors();
+        self
+    }
+
+    /// `random()` and `random(min, max)`.
+    pub fn with_random(mut self) -> InterpreterBuilder {
+        self.interpreter.add_random();
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        self.interpreter
+    }
+}
+
+/// A configured interpreter that can be shared between threads, such as
+/// by a web service evaluating requests concurrently without a lock.
+///
+/// Each evaluation runs on its own copy of the interpreter, so it sees the
+/// constants, functions and settings the environment was created with, but
+/// assignments and definitions it makes are discarded afterwards.
+pub struct Environment {
+    interpreter: Interpreter,
+    /// Mixed into each copy's random seed so concurrent evaluations don't
+    /// all draw the same numbers.
+    evaluations: AtomicU64,
+}
+
+impl Environment {
+    pub fn new(interpreter: Interpreter) -> Environment {
+        Environment {
+            interpreter,
+            evaluations: AtomicU64::new(0),
+        }
+    }
+
+    /// Evaluates `expr` on a fresh copy of the interpreter.
+    pub fn interpret(&self, expr: &Expr) -> Result<Value, CalculatorError> {
+        let mut interpreter = self.interpreter.clone();
+        let evaluation = self.evaluations.fetch_add(1, Ordering::Relaxed);
+        interpreter.rng_state ^= evaluation.wrapping_mul(0x9e3779b97f4a7c15);
+        interpreter.interpret(expr)
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
 }
 
 impl Interpreter {
+    /// An interpreter with every built-in constant and function.
     pub fn new() -> Interpreter {
-        let mut interpreter = Interpreter {
-            variables: HashMap::new(),
-            single_functions: HashMap::new(),
-            double_functions: HashMap::new(),
-        };
+        let mut interpreter = Interpreter::empty();
         interpreter.add_things();
         interpreter
     }
 
+    /// An interpreter with no built-ins at all, not even `pi`.
+    pub fn empty() -> Interpreter {
+        Interpreter {
+            scope: Scope::new(),
+            single_functions: Arc::default(),
+            double_functions: Arc::default(),
+            triple_functions: Arc::default(),
+            variadic_functions: Arc::default(),
+            stateful_functions: Arc::default(),
+            user_functions: HashMap::new(),
+            results: Vec::new(),
+            angle_mode: AngleMode::default(),
+            angle_usage: Arc::default(),
+            allow_override: false,
+            strict_mode: false,
+            epsilon: DEFAULT_EPSILON,
+            limits: Limits::default(),
+            descriptions: Arc::default(),
+            builtins: Arc::default(),
+            call_depth: 0,
+            steps: 0,
+            rng_state: clock_seed(),
+        }
+    }
+
+    /// Starts building an interpreter with only selected groups of
+    /// built-ins, e.g. `Interpreter::builder().with_constants().build()`.
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder {
+            interpreter: Interpreter::empty(),
+        }
+    }
+
     fn add_things(&mut self) {
+        self.add_constants()
+            .add_trig()
+            .add_elementary()
+            .add_combinatorics()
+            .add_statistics()
+            .add_calculus()
+            .add_vectors()
+            .add_random();
+    }
+
+    fn add_constants(&mut self) -> &mut Interpreter {
         self.add_variable("pi", consts::PI)
             .add_variable("e", consts::E)
             .add_variable("tau", consts::TAU)
             .add_variable("phi", PHI)
-            .add_single_function("sin", f64::sin)
+            .add_variable("π", consts::PI)
+            .add_variable("τ", consts::TAU)
+            .describe(
+                "pi",
+                "The ratio of a circle's circumference to its diameter.",
+            )
+            .describe("e", "Euler's number, the base of the natural logarithm.")
+            .describe("tau", "Two times pi.")
+            .describe("phi", "The golden ratio.")
+            .describe("π", "The same as pi.")
+            .describe("τ", "The same as tau.")
+    }
+
+    fn add_trig(&mut self) -> &mut Interpreter {
+        self.add_single_function("sin", f64::sin)
             .add_single_function("cos", f64::cos)
             .add_single_function("tan", f64::tan)
             .add_single_function("asin", f64::asin)
@@ -131,7 +1129,62 @@ impl Interpreter {
             .add_single_function("asinh", f64::asinh)
             .add_single_function("acosh", f64::acosh)
             .add_single_function("atanh", f64::atanh)
-            .add_single_function("sqrt", f64::sqrt)
+            .add_double_function("atan2", f64::atan2)
+            .add_double_function("hypot", f64::hypot)
+            // Explicit conversions, so they ignore the angle mode.
+            .add_single_function("deg", f64::to_degrees)
+            .add_single_function("rad", f64::to_radians)
+            .add_single_function("sind", sind)
+            .add_single_function("cosd", cosd)
+            .add_single_function("tand", tand)
+            .add_single_function("asind", |x| to_whole_degrees(x.asin()))
+            .add_single_function("acosd", |x| to_whole_degrees(x.acos()))
+            .add_single_function("atand", |x| to_whole_degrees(x.atan()))
+            .set_angle_usage("sin", AngleUsage::Input)
+            .set_angle_usage("cos", AngleUsage::Input)
+            .set_angle_usage("tan", AngleUsage::Input)
+            .set_angle_usage("asin", AngleUsage::Output)
+            .set_angle_usage("acos", AngleUsage::Output)
+            .set_angle_usage("atan", AngleUsage::Output)
+            .set_angle_usage("atan2", AngleUsage::Output)
+            .describe("sin", "sin(x): The sine of the angle x.")
+            .describe("cos", "cos(x): The cosine of the angle x.")
+            .describe("tan", "tan(x): The tangent of the angle x.")
+            .describe("asin", "asin(x): The angle whose sine is x.")
+            .describe("acos", "acos(x): The angle whose cosine is x.")
+            .describe("atan", "atan(x): The angle whose tangent is x.")
+            .describe("sinh", "sinh(x): The hyperbolic sine of x.")
+            .describe("cosh", "cosh(x): The hyperbolic cosine of x.")
+            .describe("tanh", "tanh(x): The hyperbolic tangent of x.")
+            .describe("asinh", "asinh(x): The inverse hyperbolic sine of x.")
+            .describe("acosh", "acosh(x): The inverse hyperbolic cosine of x.")
+            .describe("atanh", "atanh(x): The inverse hyperbolic tangent of x.")
+            .describe("atan2", "atan2(y, x): The angle of the point (x, y).")
+            .describe(
+                "hypot",
+                "hypot(x, y): The length of the hypotenuse, sqrt(x^2 + y^2).",
+            )
+            .describe("deg", "deg(x): The angle x radians converted to degrees.")
+            .describe("rad", "rad(x): The angle x degrees converted to radians.")
+            .describe("sind", "sind(x): The sine of x degrees, in any angle mode.")
+            .describe(
+                "cosd",
+                "cosd(x): The cosine of x degrees, in any angle mode.",
+            )
+            .describe(
+                "tand",
+                "tand(x): The tangent of x degrees, in any angle mode.",
+            )
+            .describe("asind", "asind(x): The angle in degrees whose sine is x.")
+            .describe("acosd", "acosd(x): The angle in degrees whose cosine is x.")
+            .describe(
+                "atand",
+                "atand(x): The angle in degrees whose tangent is x.",
+            )
+    }
+
+    fn add_elementary(&mut self) -> &mut Interpreter {
+        self.add_single_function("sqrt", f64::sqrt)
             .add_single_function("cbrt", f64::cbrt)
             .add_single_function("exp", f64::exp)
             .add_single_function("exp2", f64::exp2)
@@ -145,40 +1198,1038 @@ impl Interpreter {
             .add_single_function("round", f64::round)
             .add_single_function("trunc", f64::trunc)
             .add_double_function("pow", f64::powf)
-            .add_double_function("atan2", f64::atan2)
-            .add_double_function("hypot", f64::hypot)
-            .add_double_function("max", f64::max)
+            .add_double_function("log", f64::log)
+            .add_double_function("root", root)
+            .add_double_function("round", round_to)
+            .add_double_function("remainder", remainder)
+            .add_double_function("fmod", fmod)
+            .add_double_function("mod", floored_mod)
+            .add_double_function("rem", fmod)
+            .add_triple_function("clamp", clamp)
+            .describe("sqrt", "sqrt(x): The square root of x.")
+            .describe("cbrt", "cbrt(x): The cube root of x.")
+            .describe("exp", "exp(x): e raised to the power x.")
+            .describe("exp2", "exp2(x): 2 raised to the power x.")
+            .describe("ln", "ln(x): The natural logarithm of x.")
+            .describe("log2", "log2(x): The base 2 logarithm of x.")
+            .describe("log10", "log10(x): The base 10 logarithm of x.")
+            .describe("abs", "abs(x): The absolute value of x.")
+            .describe("signum", "signum(x): 1 or -1 depending on the sign of x.")
+            .describe("floor", "floor(x): The largest integer not above x.")
+            .describe("ceil", "ceil(x): The smallest integer not below x.")
+            .describe("round", "round(x) or round(x, digits): x rounded to the nearest integer or to digits decimal places.")
+            .describe("trunc", "trunc(x): x with its fractional part removed.")
+            .describe("pow", "pow(x, y): x raised to the power y.")
+            .describe("log", "log(x, base): The logarithm of x in the given base.")
+            .describe("root", "root(x, n): The nth root of x.")
+            .describe("remainder", "remainder(x, y): The IEEE remainder of x divided by y.")
+            .describe("fmod", "fmod(x, y): The remainder of x divided by y, with the sign of x.")
+            .describe("mod", "mod(x, y): x modulo y, with the sign of y.")
+            .describe("rem", "rem(x, y): The same as fmod.")
+            .describe("clamp", "clamp(x, lo, hi): x limited to the range lo to hi.")
+    }
+
+    fn add_combinatorics(&mut self) -> &mut Interpreter {
+        self.add_double_function("gcd", gcd)
+            .add_double_function("lcm", lcm)
+            .add_double_function("ncr", ncr)
+            .add_double_function("npr", npr)
+            .describe(
+                "gcd",
+                "gcd(a, b): The greatest common divisor of two integers.",
+            )
+            .describe(
+                "lcm",
+                "lcm(a, b): The least common multiple of two integers.",
+            )
+            .describe(
+                "ncr",
+                "ncr(n, r): The number of ways to choose r of n items.",
+            )
+            .describe(
+                "npr",
+                "npr(n, r): The number of ways to arrange r of n items.",
+            )
+    }
+
+    /// Also enables the `sum` and `prod` series special forms.
+    fn add_statistics(&mut self) -> &mut Interpreter {
+        self.add_double_function("max", f64::max)
             .add_double_function("min", f64::min)
-            .add_double_function("remainder", f64::rem_euclid)
-            .add_double_function("fmod", f64::rem_euclid);
+            .add_variadic_function("sum", |args| args.iter().sum())
+            .add_variadic_function("prod", |args| args.iter().product())
+            .add_variadic_function("mean", mean)
+            .add_variadic_function("max", |args| args.iter().copied().fold(f64::NAN, f64::max))
+            .add_variadic_function("min", |args| args.iter().copied().fold(f64::NAN, f64::min))
+            .add_variadic_function("median", median)
+            .add_variadic_function("stddev", stddev)
+            .describe("max", "max(a, b, ...): The largest argument.")
+            .describe("min", "min(a, b, ...): The smallest argument.")
+            .describe("sum", "sum(a, b, ...) or sum(i, from, to, body): The sum of the arguments, or of body for each integer i.")
+            .describe("prod", "prod(a, b, ...) or prod(i, from, to, body): The product of the arguments, or of body for each integer i.")
+            .describe("mean", "mean(a, b, ...): The arithmetic mean of the arguments.")
+            .describe("median", "median(a, b, ...): The middle argument once sorted.")
+            .describe("stddev", "stddev(a, b, ...): The population standard deviation.")
+    }
+
+    fn add_calculus(&mut self) -> &mut Interpreter {
+        self.add_special_form("deriv").describe(
+            "deriv",
+            "deriv(body, x, point[, h]): The derivative of body with respect to x at point.",
+        )
+    }
+
+    fn add_vectors(&mut self) -> &mut Interpreter {
+        self.add_special_form("dot")
+            .add_special_form("len")
+            .describe("dot", "dot(a, b): The dot product of the vectors a and b.")
+            .describe("len", "len(v): The number of elements in the vector v.")
+    }
+
+    fn add_random(&mut self) -> &mut Interpreter {
+        self.add_stateful_function("random", &[0, 2], |interpreter, args| {
+            let unit = interpreter.random();
+            match args {
+                [min, max] => min + (max - min) * unit,
+                _ => unit,
+            }
+        })
+        .describe(
+            "random",
+            "random() or random(min, max): A uniformly distributed random number.",
+        )
     }
 
     /// Simple utility function to add a variable to the interpreter
     /// Returns self for chaining.
-    fn add_variable(&mut self, name: &str, value: f64) -> &mut Interpreter {
-        self.variables.insert(name.to_string(), value);
+    pub fn add_variable(&mut self, name: &str, value: f64) -> &mut Interpreter {
+        self.scope.define(name, value.into());
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
         self
     }
 
     /// Simple utility function to add a single argument function to the interpreter
     /// Returns self for chaining.
-    fn add_single_function(&mut self, name: &str, function: fn(f64) -> f64) -> &mut Interpreter {
-        self.single_functions.insert(name.to_string(), function);
+    pub fn add_single_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(f64) -> f64 + Send + Sync + 'static,
+    ) -> &mut Interpreter {
+        Arc::make_mut(&mut self.single_functions).insert(name.to_string(), Arc::new(function));
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
         self
     }
 
     /// Simple utility function to add a double argument function to the interpreter
     /// Returns self for chaining.
-    fn add_double_function(
+    pub fn add_double_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(f64, f64) -> f64 + Send + Sync + 'static,
+    ) -> &mut Interpreter {
+        Arc::make_mut(&mut self.double_functions).insert(name.to_string(), Arc::new(function));
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
+        self
+    }
+
+    /// Simple utility function to add a triple argument function to the interpreter
+    /// Returns self for chaining.
+    pub fn add_triple_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(f64, f64, f64) -> f64 + Send + Sync + 'static,
+    ) -> &mut Interpreter {
+        Arc::make_mut(&mut self.triple_functions).insert(name.to_string(), Arc::new(function));
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
+        self
+    }
+
+    /// Simple utility function to add a function taking one or more arguments
+    /// to the interpreter. Returns self for chaining.
+    pub fn add_variadic_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[f64]) -> f64 + Send + Sync + 'static,
+    ) -> &mut Interpreter {
+        Arc::make_mut(&mut self.variadic_functions).insert(name.to_string(), Arc::new(function));
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
+        self
+    }
+
+    /// Marks the function `name` as taking or returning angles, so its
+    /// arguments or result are converted in degrees mode.
+    /// Returns self for chaining.
+    pub fn set_angle_usage(&mut self, name: &str, usage: AngleUsage) -> &mut Interpreter {
+        Arc::make_mut(&mut self.angle_usage).insert(name.to_string(), usage);
+        self
+    }
+
+    /// Simple utility function to add a function that can access the
+    /// interpreter, accepting any of the argument counts in `arities`.
+    /// Returns self for chaining.
+    pub fn add_stateful_function(
         &mut self,
         name: &str,
-        function: fn(f64, f64) -> f64,
+        arities: &[usize],
+        function: impl Fn(&mut Interpreter, &[f64]) -> f64 + Send + Sync + 'static,
     ) -> &mut Interpreter {
-        self.double_functions.insert(name.to_string(), function);
+        Arc::make_mut(&mut self.stateful_functions)
+            .insert(name.to_string(), (arities.to_vec(), Arc::new(function)));
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
         self
     }
 
-    pub fn interpret(&mut self, expr: &dyn Expression) -> Result<f64, CalculatorError> {
-        expr.accept(self)
+    /// Attaches a one-line description to `name` for `:help`.
+    /// Returns self for chaining.
+    pub fn describe(&mut self, name: &str, description: &str) -> &mut Interpreter {
+        Arc::make_mut(&mut self.descriptions).insert(name.to_string(), description.to_string());
+        self
+    }
+
+    /// Reserves the name of a special form handled by `special_form`, so it
+    /// can't be assigned to. Returns self for chaining.
+    fn add_special_form(&mut self, name: &str) -> &mut Interpreter {
+        Arc::make_mut(&mut self.builtins).insert(name.to_string());
+        self
+    }
+
+    /// Restarts the random number generator from `seed`, so the numbers
+    /// returned by `random` are reproducible.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng_state = seed;
+    }
+
+    /// Returns a uniformly distributed number in `[0, 1)` using SplitMix64.
+    pub fn random(&mut self) -> f64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        // The top 53 bits fill an f64's mantissa exactly.
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Evaluates calls whose arguments aren't all evaluated up front, such as
+    /// `sum(i, 1, 5, i^2)`. Returns `None` if `expr` is an ordinary call.
+    ///
+    /// `sum` and `prod` are only treated as series when called with four
+    /// arguments, the first a bare variable, so `sum(x, 1, 2, 3)` is a
+    /// series even if `x` is defined.
+    fn special_form(&mut self, expr: &Call) -> Option<Result<Value, CalculatorError>> {
+        let name = expr.callee.lexeme.as_str();
+        // Special forms only exist if registered, and a user function of the
+        // same name takes precedence.
+        if !self.builtins.contains(name) || self.user_functions.contains_key(name) {
+            return None;
+        }
+        match (name, expr.arguments.as_slice()) {
+            // Without a bound variable this is the variadic `sum(a, b, ...)`,
+            // so it falls through to the ordinary call.
+            ("sum" | "prod", [variable, start, end, body]) => {
+                let variable = variable.as_variable()?;
+                Some(self.series(expr, variable, start, end, body))
+            }
+            ("deriv", [body, variable, point]) => {
+                let variable = match self.bound_variable(expr, variable) {
+                    Ok(variable) => variable,
+                    Err(error) => return Some(Err(error)),
+                };
+                let h = DERIVATIVE_STEP.into();
+                Some(self.derivative(expr, body, variable, point, h))
+            }
+            ("deriv", [body, variable, point, h]) => {
+                let variable = match self.bound_variable(expr, variable) {
+                    Ok(variable) => variable,
+                    Err(error) => return Some(Err(error)),
+                };
+                let h = match self.evaluate(h) {
+                    Ok(h) => h,
+                    Err(error) => return Some(Err(error)),
+                };
+                Some(self.derivative(expr, body, variable, point, h))
+            }
+            ("deriv", _) => Some(Err(Self::arity_mismatch(expr, 3))),
+            ("dot", [left, right]) => Some(self.dot(expr, left, right)),
+            ("len", [vector]) => Some(
+                self.evaluate(vector)
+                    .and_then(|vector| Ok((vector.to_vector(&expr.callee)?.len() as f64).into())),
+            ),
+            ("dot", _) => Some(Err(Self::arity_mismatch(expr, 2))),
+            ("len", _) => Some(Err(Self::arity_mismatch(expr, 1))),
+            _ => None,
+        }
+    }
+
+    /// A wrong number of arguments to the special form called by `expr`,
+    /// which takes `expected`.
+    fn arity_mismatch(expr: &Call, expected: usize) -> CalculatorError {
+        CalculatorError {
+            error: CalculatorErrorType::FunctionArityMismatch(
+                expr.callee.lexeme.to_string(),
+                expr.arguments.len(),
+                expected,
+            ),
+            token: Some(expr.callee.clone()),
+        }
+    }
+
+    /// The name in `variable`, or an error at `expr`'s callee if it is
+    /// anything other than a plain name.
+    fn bound_variable<'a>(
+        &self,
+        expr: &Call,
+        variable: &'a Expr,
+    ) -> Result<&'a Token, CalculatorError> {
+        variable.as_variable().ok_or_else(|| CalculatorError {
+            error: CalculatorErrorType::ExpectedVariableName(expr.callee.lexeme.to_string()),
+            token: Some(expr.callee.clone()),
+        })
+    }
+
+    /// The dot product of two vectors of the same length.
+    fn dot(&mut self, expr: &Call, left: &Expr, right: &Expr) -> Result<Value, CalculatorError> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        let left = left.to_vector(&expr.callee)?;
+        let right = right.to_vector(&expr.callee)?;
+        if left.len() != right.len() {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::LengthMismatch(left.len(), right.len()),
+                token: Some(expr.callee.clone()),
+            });
+        }
+        Ok(left
+            .iter()
+            .zip(right)
+            .map(|(a, b)| a * b)
+            .sum::<f64>()
+            .into())
+    }
+
+    /// Adds or multiplies `body` for each integer `variable` from `start` up
+    /// to `end`. An empty range gives 0 for `sum` and 1 for `prod`. The
+    /// bounds must be integers that fit in an `i64`, and the range may hold
+    /// at most `MAX_SERIES_TERMS` of them.
+    fn series(
+        &mut self,
+        expr: &Call,
+        variable: &Token,
+        start: &Expr,
+        end: &Expr,
+        body: &Expr,
+    ) -> Result<Value, CalculatorError> {
+        let domain_error = |bound: f64| CalculatorError {
+            error: CalculatorErrorType::DomainError(expr.callee.lexeme.to_string(), bound),
+            token: Some(expr.callee.clone()),
+        };
+        // Counting in an `i64` rather than an `f64` means the loop can't get
+        // stuck past 2^53, where adding 1 no longer changes the float.
+        let bound = |bound: f64| {
+            if bound.fract() == 0.0 && bound.abs() < i64::MAX as f64 {
+                Ok(bound as i64)
+            } else {
+                Err(domain_error(bound))
+            }
+        };
+        let start = bound(self.evaluate(start)?.to_number(&expr.callee)?)?;
+        let end = bound(self.evaluate(end)?.to_number(&expr.callee)?)?;
+        if end as i128 - start as i128 >= MAX_SERIES_TERMS as i128 {
+            return Err(domain_error(end as f64));
+        }
+        let product = expr.callee.lexeme == "prod";
+        let mut result = if product { 1.0 } else { 0.0 };
+
+        self.push_scope();
+        for i in start..=end {
+            self.scope.define(&variable.lexeme, (i as f64).into());
+            let term = match self
+                .evaluate(body)
+                .and_then(|value| value.to_number(&expr.callee))
+            {
+                Ok(term) => term,
+                Err(error) => {
+                    self.pop_scope();
+                    return Err(error);
+                }
+            };
+            if product {
+                result *= term;
+            } else {
+                result += term;
+            }
+        }
+        self.pop_scope();
+        Ok(result.into())
+    }
+
+    /// Approximates the derivative of `body` with respect to `variable` at
+    /// `point` with the central difference `(f(x + h) - f(x - h)) / 2h`.
+    ///
+    /// The truncation error shrinks with `h^2`, but rounding error grows as
+    /// `h` gets smaller, so results are typically accurate to 8-10
+    /// significant digits and worse near discontinuities or for very large
+    /// `point`s.
+    fn derivative(
+        &mut self,
+        expr: &Call,
+        body: &Expr,
+        variable: &Token,
+        point: &Expr,
+        h: Value,
+    ) -> Result<Value, CalculatorError> {
+        let point = self.evaluate(point)?.to_number(&expr.callee)?;
+        let h = h.to_number(&expr.callee)?;
+        if h <= 0.0 || !h.is_finite() {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::DomainError(expr.callee.lexeme.to_string(), h),
+                token: Some(expr.callee.clone()),
+            });
+        }
+
+        self.push_scope();
+        let mut evaluate = |x: f64| {
+            self.scope.define(&variable.lexeme, x.into());
+            self.evaluate(body)
+                .and_then(|value| value.to_number(&expr.callee))
+        };
+        let result =
+            evaluate(point + h).and_then(|above| Ok((above - evaluate(point - h)?) / (2.0 * h)));
+        self.pop_scope();
+        Ok(result?.into())
+    }
+
+    /// A NaN from non-NaN arguments means they were outside the function's
+    /// domain, e.g. `sqrt(-1)` or `asin(2)`.
+    fn check_domain(
+        &self,
+        expr: &Call,
+        arguments: &[f64],
+        result: f64,
+    ) -> Result<Value, CalculatorError> {
+        if result.is_nan() && !arguments.iter().any(|argument| argument.is_nan()) {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::DomainError(
+                    expr.callee.lexeme.to_string(),
+                    arguments.first().copied().unwrap_or(f64::NAN),
+                ),
+                token: Some(expr.callee.clone()),
+            });
+        }
+        Ok(result.into())
+    }
+
+    /// Evaluates the body of a user-defined function in a child scope with
+    /// its parameters bound to `arguments`.
+    fn call_user_function(
+        &mut self,
+        expr: &Call,
+        parameters: &[String],
+        body: &Expr,
+        arguments: Vec<f64>,
+    ) -> Result<Value, CalculatorError> {
+        if self.call_depth >= self.limits.max_call_depth {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::RecursionTooDeep(expr.callee.lexeme.to_string()),
+                token: Some(expr.callee.clone()),
+            });
+        }
+
+        self.push_scope();
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            self.scope.define(parameter, argument.into());
+        }
+        self.call_depth += 1;
+        let result = self.evaluate(body);
+        self.call_depth -= 1;
+        self.pop_scope();
+        result
+    }
+
+    fn push_scope(&mut self) {
+        let parent = std::mem::take(&mut self.scope);
+        self.scope = Scope::with_parent(parent);
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = std::mem::take(&mut self.scope);
+        self.scope = scope
+            .into_parent()
+            .expect("pop_scope called on the global scope");
+    }
+
+    /// The names of every callable function, built-in or user-defined,
+    /// sorted and without duplicates.
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .single_functions
+            .keys()
+            .chain(self.double_functions.keys())
+            .chain(self.triple_functions.keys())
+            .chain(self.variadic_functions.keys())
+            .chain(self.stateful_functions.keys())
+            .chain(self.user_functions.keys())
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The names of the built-in constants, sorted.
+    pub fn constant_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .scope
+            .variables
+            .keys()
+            .filter(|name| self.builtins.contains(*name))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// How many arguments the function `name` accepts, or `None` if there is
+    /// no such function. User-defined functions shadow built-ins, and a
+    /// variadic overload accepts every count the fixed ones do.
+    pub fn arity(&self, name: &str) -> Option<Arity> {
+        if let Some(function) = self.user_functions.get(name) {
+            return Some(Arity::from_count(function.parameters.len()));
+        }
+        if let Some((arities, _)) = self.stateful_functions.get(name) {
+            let mut arities: Vec<_> = arities.iter().copied().map(Arity::from_count).collect();
+            return if arities.len() == 1 {
+                arities.pop()
+            } else {
+                Some(Arity::Overloaded(arities))
+            };
+        }
+        if self.variadic_functions.contains_key(name) {
+            return Some(Arity::Variadic);
+        }
+        let mut arities = Vec::new();
+        if self.single_functions.contains_key(name) {
+            arities.push(Arity::Single);
+        }
+        if self.double_functions.contains_key(name) {
+            arities.push(Arity::Double);
+        }
+        if self.triple_functions.contains_key(name) {
+            arities.push(Arity::Triple);
+        }
+        match arities.len() {
+            0 => None,
+            1 => arities.pop(),
+            _ => Some(Arity::Overloaded(arities)),
+        }
+    }
+
+    /// The user's variables and functions as lines of source that recreate
+    /// them when evaluated, such as `x = 2` and `f(x) = ((x ^ 2) + 1)`.
+    /// Built-ins and the `ans` result are left out, and variables come before
+    /// functions, each sorted by name.
+    pub fn definitions(&self) -> Vec<String> {
+        let mut variables: Vec<_> = self
+            .scope
+            .variables
+            .iter()
+            .filter(|(name, _)| !self.builtins.contains(*name) && *name != "ans" && *name != "_")
+            .filter_map(|(name, value)| {
+                let value = match value {
+                    Value::Number(number) => number_source(*number),
+                    Value::Vector(vector) => {
+                        let elements: Vec<_> =
+                            vector.iter().map(|number| number_source(*number)).collect();
+                        format!("[{}]", elements.join(", "))
+                    }
+                    Value::Unit => return None,
+                    value => value.to_string(),
+                };
+                Some(format!("{} = {}", name, value))
+            })
+            .collect();
+        variables.sort();
+        let mut functions: Vec<_> = self
+            .user_functions
+            .iter()
+            .map(|(name, function)| {
+                format!(
+                    "{}({}) = {}",
+                    name,
+                    function.parameters.join(", "),
+                    function.body.display()
+                )
+            })
+            .collect();
+        functions.sort();
+        variables.extend(functions);
+        variables
+    }
+
+    /// Removes the user-defined variable or function `name`, returning
+    /// whether there was one. Built-in constants can't be removed.
+    pub fn forget(&mut self, name: &str) -> bool {
+        let function = self.user_functions.remove(name).is_some();
+        let variable = !self.builtins.contains(name) && self.scope.variables.remove(name).is_some();
+        function || variable
+    }
+
+    /// Stores the result of a successful evaluation as `ans` (and `_`) so
+    /// the next expression can refer to it, and as `_n` for the `n`th
+    /// result so any later one can.
+    pub fn record_result(&mut self, value: Value) {
+        self.scope.define("ans", value.clone());
+        self.scope.define("_", value.clone());
+        self.results.push(value);
+    }
+
+    /// Evaluates the expressions of a program in order, sharing variables
+    /// between them, and returns the value of the last one. Evaluation stops
+    /// at the first error.
+    pub fn interpret_program(&mut self, program: &[Expr]) -> Result<Value, CalculatorError> {
+        let mut result = Value::Unit;
+        for expr in program {
+            result = self.interpret(expr)?;
+        }
+        Ok(result)
+    }
+
+    /// Evaluates a complete expression. In `strict_mode` a NaN or infinite
+    /// result is reported as `NonFiniteResult` instead of being returned.
+    pub fn interpret(&mut self, expr: &Expr) -> Result<Value, CalculatorError> {
+        if let Some(max_nodes) = self.limits.max_nodes {
+            if expr.node_count() > max_nodes {
+                return Err(CalculatorError {
+                    error: CalculatorErrorType::ResourceLimitExceeded("max_nodes".to_string()),
+                    token: None,
+                });
+            }
+        }
+        self.steps = 0;
+        let value = self.evaluate(expr)?;
+        match value {
+            Value::Number(number) if self.strict_mode && !number.is_finite() => {
+                Err(CalculatorError {
+                    error: CalculatorErrorType::NonFiniteResult(number),
+                    token: None,
+                })
+            }
+            value => Ok(value),
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, CalculatorError> {
+        self.steps += 1;
+        if self
+            .limits
+            .max_steps
+            .is_some_and(|max_steps| self.steps > max_steps)
+        {
+            return Err(CalculatorError {
+                error: CalculatorErrorType::ResourceLimitExceeded("max_steps".to_string()),
+                token: None,
+            });
+        }
+        match expr {
+            Expr::Binary(expr) => self.evaluate_binary(expr),
+            Expr::Logical(expr) => self.evaluate_logical(expr),
+            Expr::Conditional(expr) => self.evaluate_conditional(expr),
+            Expr::Comparison(expr) => self.evaluate_comparison(expr),
+            Expr::Grouping(expr) => self.evaluate_grouping(expr),
+            Expr::List(expr) => self.evaluate_list(expr),
+            Expr::Index(expr) => self.evaluate_index(expr),
+            Expr::Literal(expr) => self.evaluate_literal(expr),
+            Expr::Unary(expr) => self.evaluate_unary(expr),
+            Expr::Call(expr) => self.evaluate_call(expr),
+            Expr::Variable(expr) => self.evaluate_variable(expr),
+            Expr::Assignment(expr) => self.evaluate_assignment(expr),
+            Expr::FunctionDefinition(expr) => self.evaluate_function_definition(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::Value;
+
+    use super::*;
+
+    fn value(source: &str) -> f64 {
+        match crate::eval(source).unwrap() {
+            Value::Number(number) => number,
+            value => panic!("expected a number but got {}", value),
+        }
+    }
+
+    fn truth(source: &str) -> bool {
+        match crate::eval(source).unwrap() {
+            Value::Bool(bool) => bool,
+            value => panic!("expected a boolean but got {}", value),
+        }
+    }
+
+    fn error(source: &str) -> CalculatorErrorType {
+        crate::eval(source).unwrap_err().error
+    }
+
+    #[test]
+    fn modulo_takes_the_sign_of_the_left_operand() {
+        assert_eq!(value("10 % 3"), 1.0);
+        assert_eq!(value("-7 % 3"), -1.0);
+        assert_eq!(value("7 % (-3)"), 1.0);
+        assert_eq!(value("-7 % (-3)"), -1.0);
+        assert_eq!(error("5 % 0"), CalculatorErrorType::DivisionByZero);
+    }
+
+    #[test]
+    fn functions_raise_domain_errors_outside_their_domain() {
+        let domain_error =
+            |name: &str, x: f64| CalculatorErrorType::DomainError(name.to_string(), x);
+        assert_eq!(error("sqrt(-1)"), domain_error("sqrt", -1.0));
+        assert_eq!(value("sqrt(0)"), 0.0);
+        assert_eq!(error("ln(-5)"), domain_error("ln", -5.0));
+        assert_eq!(value("ln(1)"), 0.0);
+        assert_eq!(error("log2(-1)"), domain_error("log2", -1.0));
+        assert_eq!(error("log10(-1)"), domain_error("log10", -1.0));
+        assert_eq!(error("asin(1.5)"), domain_error("asin", 1.5));
+        assert_eq!(value("asin(1)"), std::f64::consts::FRAC_PI_2);
+        assert_eq!(error("acos(-1.5)"), domain_error("acos", -1.5));
+        assert_eq!(value("acos(-1)"), std::f64::consts::PI);
+        // NaN in gives NaN out without blaming the function.
+        assert!(value("sqrt(1e309 - 1e309)").is_nan());
+    }
+
+    #[test]
+    fn fmod_truncates_and_remainder_rounds_to_nearest() {
+        assert_eq!(value("fmod(-5, 3)"), -2.0);
+        assert_eq!(value("fmod(5, -3)"), 2.0);
+        assert_eq!(value("fmod(5.5, 2)"), 1.5);
+        assert_eq!(value("remainder(5, 3)"), -1.0);
+        assert_eq!(value("remainder(-5, 3)"), 1.0);
+        // 3.5 rounds to the even 4, as in IEEE 754.
+        assert_eq!(value("remainder(7, 2)"), -1.0);
+        assert_eq!(value("remainder(5, 2)"), 1.0);
+    }
+
+    #[test]
+    fn log_takes_a_base() {
+        assert_eq!(value("log(8, 2)"), 3.0);
+        assert!((value("log(27, 3)") - 3.0).abs() < 1e-12);
+        assert_eq!(value("log(1, 10)"), 0.0);
+        assert_eq!(
+            error("log(100)"),
+            CalculatorErrorType::FunctionArityMismatch("log".to_string(), 1, 2)
+        );
+    }
+
+    #[test]
+    fn root_takes_real_odd_roots_of_negatives() {
+        assert_eq!(value("root(27, 3)"), 3.0);
+        assert_eq!(value("root(16, 4)"), 2.0);
+        assert_eq!(value("root(-8, 3)"), -2.0);
+        assert_eq!(value("root(-27, 3)"), -3.0);
+        assert_eq!(
+            error("root(-4, 2)"),
+            CalculatorErrorType::DomainError("root".to_string(), -4.0)
+        );
+    }
+
+    #[test]
+    fn clamp_limits_to_the_range() {
+        assert_eq!(value("clamp(-5, 0, 10)"), 0.0);
+        assert_eq!(value("clamp(5, 0, 10)"), 5.0);
+        assert_eq!(value("clamp(15, 0, 10)"), 10.0);
+        assert_eq!(
+            error("clamp(5, 10, 0)"),
+            CalculatorErrorType::DomainError("clamp".to_string(), 5.0)
+        );
+    }
+
+    #[test]
+    fn ncr_and_npr_count_exactly() {
+        assert_eq!(value("ncr(5, 2)"), 10.0);
+        assert_eq!(value("npr(5, 2)"), 20.0);
+        assert_eq!(value("ncr(10, 0)"), 1.0);
+        assert_eq!(value("ncr(10, 3)"), value("ncr(10, 7)"));
+        assert_eq!(value("ncr(60, 30)"), 118264581564861424.0);
+        assert_eq!(value("ncr(2000, 1000)"), f64::INFINITY);
+        let domain_error =
+            |name: &str, x: f64| CalculatorErrorType::DomainError(name.to_string(), x);
+        assert_eq!(error("ncr(3, 5)"), domain_error("ncr", 3.0));
+        assert_eq!(error("ncr(-1, 2)"), domain_error("ncr", -1.0));
+        assert_eq!(error("ncr(5.5, 2)"), domain_error("ncr", 5.5));
+        assert_eq!(error("npr(5, 6)"), domain_error("npr", 5.0));
+    }
+
+    #[test]
+    fn series_need_integer_bounds_and_a_bounded_range() {
+        assert_eq!(value("sum(i, 1, 5, i^2)"), 55.0);
+        assert_eq!(value("prod(i, 1, 5, i)"), 120.0);
+        assert_eq!(value("sum(i, 5, 1, i)"), 0.0);
+        assert_eq!(value("prod(i, 5, 1, i)"), 1.0);
+        // Past 2^53 a float counter stops advancing.
+        assert_eq!(value("sum(i, 1e16, 1e16 + 10, 1)"), 11.0);
+        let domain_error = |x: f64| CalculatorErrorType::DomainError("sum".to_string(), x);
+        assert_eq!(error("sum(i, 0.5, 3, i)"), domain_error(0.5));
+        assert_eq!(error("sum(i, 1, 1e300, 1)"), domain_error(1e300));
+        assert_eq!(error("sum(i, 1, 1e309, 1)"), domain_error(f64::INFINITY));
+    }
+
+    #[test]
+    fn deriv_needs_a_plain_variable_name() {
+        assert!((value("deriv(sin(x), x, 0)") - 1.0).abs() < 1e-9);
+        assert!((value("deriv(x^2, x, 3, 1e-3)") - 6.0).abs() < 1e-9);
+        let expected = CalculatorErrorType::ExpectedVariableName("deriv".to_string());
+        assert_eq!(error("deriv(x, 2, 1)"), expected);
+        assert_eq!(error("deriv(x, 2, 1, 1e-3)"), expected);
+    }
+
+    #[test]
+    fn comparisons_chain_like_in_mathematics() {
+        assert!(truth("1 < 5 < 10"));
+        assert!(!truth("1 < 20 < 10"));
+        assert!(truth("3 > 2 > 1"));
+        assert!(truth("1 <= 1 < 2 == 2"));
+        assert!(!truth("0 < -1 < 10"));
+    }
+
+    #[test]
+    fn chained_comparisons_stop_at_the_first_failure() {
+        assert!(!truth("2 < 1 < 1 / 0"));
+        assert_eq!(error("1 < 2 < 1 / 0"), CalculatorErrorType::DivisionByZero);
+    }
+
+    #[test]
+    fn conditionals_evaluate_only_the_chosen_branch() {
+        let abs = |x: f64| {
+            let variables = [("x".to_string(), x)].into();
+            crate::eval_with("x > 0 ? x : -x", &variables).unwrap()
+        };
+        assert_eq!(abs(5.0), Value::Number(5.0));
+        assert_eq!(abs(-3.0), Value::Number(3.0));
+        assert_eq!(value("0 ? 2 : 1 ? 3 : 4"), 3.0);
+        assert_eq!(value("true ? false ? 1 : 2 : 3"), 2.0);
+        assert_eq!(value("false ? 1 / 0 : 2"), 2.0);
+        assert_eq!(value("true ? 2 : 1 / 0"), 2.0);
+    }
+
+    #[test]
+    fn a_function_needs_parentheses_to_be_called() {
+        assert_eq!(value("sin  (  0  )"), 0.0);
+        assert_eq!(
+            error("sin"),
+            CalculatorErrorType::UndefinedVariableOrFunction("sin".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn call_errors_point_at_the_function_name() {
+        let error = crate::eval("1 +\n  sin(1, 2)").unwrap_err();
+        assert_eq!(
+            error.error,
+            CalculatorErrorType::FunctionArityMismatch("sin".to_string(), 2, 1)
+        );
+        assert!(error.to_string().starts_with("(At 'sin' in line 2:3)"));
+        let error = crate::eval("foo(1)").unwrap_err();
+        assert!(error.to_string().starts_with("(At 'foo' in line 1:1)"));
+    }
+
+    #[test]
+    fn negative_zero_is_still_a_zero_divisor() {
+        assert_eq!(value("-0"), 0.0);
+        assert_eq!(value("0 * -1"), 0.0);
+        assert!(truth("-0 == 0"));
+        assert_eq!(error("1 / (0 * -1)"), CalculatorErrorType::DivisionByZero);
+        assert_eq!(error("1 / -0"), CalculatorErrorType::DivisionByZero);
+    }
+
+    #[test]
+    fn deg_and_rad_convert_angles() {
+        assert_eq!(value("deg(pi)"), 180.0);
+        assert_eq!(value("rad(180)"), std::f64::consts::PI);
+        for x in ["1.234", "-0.5", "37", "1e-6"] {
+            let x: f64 = x.parse().unwrap();
+            let variables = [("x".to_string(), x)].into();
+            for source in ["rad(deg(x))", "deg(rad(x))"] {
+                let Value::Number(round_trip) = crate::eval_with(source, &variables).unwrap()
+                else {
+                    panic!("expected a number");
+                };
+                assert!(
+                    approx_eq(round_trip, x, DEFAULT_EPSILON),
+                    "{} with x = {}",
+                    source,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn degree_trigonometry_is_exact_at_familiar_angles() {
+        assert_eq!(value("sind(30)"), 0.5);
+        assert_eq!(value("sind(-30)"), -0.5);
+        assert_eq!(value("sind(390)"), 0.5);
+        assert_eq!(value("sind(180)"), 0.0);
+        assert_eq!(value("cosd(60)"), 0.5);
+        assert_eq!(value("cosd(90)"), 0.0);
+        assert_eq!(value("tand(45)"), 1.0);
+        assert_eq!(
+            error("tand(90)"),
+            CalculatorErrorType::DomainError("tand".to_string(), 90.0)
+        );
+        assert_eq!(value("asind(0.5)"), 30.0);
+        assert_eq!(value("acosd(0.5)"), 60.0);
+        assert_eq!(value("atand(1)"), 45.0);
+        // Angles that aren't whole degrees are left unrounded.
+        assert!((value("sind(10)") - 10f64.to_radians().sin()).abs() < 1e-15);
+        assert!((value("asind(0.3)") - 0.3f64.asin().to_degrees()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn approx_eq_is_relative() {
+        assert!(approx_eq(0.1 + 0.2, 0.3, DEFAULT_EPSILON));
+        assert!(approx_eq(1e20 + 1e6, 1e20, DEFAULT_EPSILON));
+        assert!(!approx_eq(1.0, 1.0 + 1e-9, DEFAULT_EPSILON));
+        assert!(!approx_eq(1e-300, 0.0, DEFAULT_EPSILON));
+        assert!(approx_eq(f64::INFINITY, f64::INFINITY, DEFAULT_EPSILON));
+        assert!(!approx_eq(f64::NAN, f64::NAN, DEFAULT_EPSILON));
+        assert!(!approx_eq(0.1 + 0.2, 0.3, 0.0));
+    }
+
+    #[test]
+    fn comparisons_use_the_interpreters_epsilon() {
+        assert!(truth("0.1 + 0.2 == 0.3"));
+        assert!(!truth("0.1 + 0.2 != 0.3"));
+        assert!(!truth("0.1 + 0.2 > 0.3"));
+        assert!(truth("0.1 + 0.2 <= 0.3"));
+        let expr = crate::parse("0.1 + 0.2 == 0.3").unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.epsilon = 0.0;
+        assert_eq!(interpreter.interpret(&expr).unwrap(), Value::Bool(false));
+    }
+
+    #[test]
+    fn mod_floors_and_rem_truncates() {
+        assert_eq!(value("mod(-7, 3)"), 2.0);
+        assert_eq!(value("rem(-7, 3)"), -1.0);
+        assert_eq!(value("mod(7, -3)"), -2.0);
+        assert_eq!(value("rem(7, -3)"), 1.0);
+        assert_eq!(value("mod(7, 3)"), value("rem(7, 3)"));
+        assert_eq!(value("mod(7.5, 2)"), value("rem(7.5, 2)"));
+    }
+
+    #[test]
+    fn indexing_is_zero_based_and_bounds_checked() {
+        assert_eq!(value("[10, 20, 30][0]"), 10.0);
+        assert_eq!(value("[10, 20, 30][1]"), 20.0);
+        assert_eq!(value("[10, 20, 30][2]"), 30.0);
+        assert_eq!(value("[10, 20, 30][len([1, 2])]"), 30.0);
+        assert_eq!(
+            error("[10, 20, 30][3]"),
+            CalculatorErrorType::IndexOutOfBounds(3.0, 3)
+        );
+        assert_eq!(
+            error("[10, 20, 30][-1]"),
+            CalculatorErrorType::IndexOutOfBounds(-1.0, 3)
+        );
+        assert_eq!(
+            error("[][0]"),
+            CalculatorErrorType::IndexOutOfBounds(0.0, 0)
+        );
+        assert_eq!(
+            error("[10, 20, 30][1.5]"),
+            CalculatorErrorType::DomainError("[".to_string(), 1.5)
+        );
+        assert!(matches!(error("5[0]"), CalculatorErrorType::TypeError(_)));
+    }
+
+    #[test]
+    fn seeding_makes_random_reproducible() {
+        let draws = |seed: u64| {
+            let mut interpreter = Interpreter::new();
+            interpreter.seed(seed);
+            ["random()", "random()", "random(10, 20)", "random(-1, 1)"]
+                .iter()
+                .map(|source| {
+                    let expr = crate::parse(source).unwrap();
+                    match interpreter.interpret(&expr).unwrap() {
+                        Value::Number(number) => number,
+                        value => panic!("expected a number but got {}", value),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = draws(42);
+        assert_eq!(first, draws(42));
+        assert_ne!(first, draws(43));
+        assert!((0.0..1.0).contains(&first[0]) && (0.0..1.0).contains(&first[1]));
+        assert_ne!(first[0], first[1]);
+        assert!((10.0..20.0).contains(&first[2]));
+        assert!((-1.0..1.0).contains(&first[3]));
+    }
+
+    #[test]
+    fn deriv_with_the_wrong_number_of_arguments_is_an_arity_mismatch() {
+        let mismatch = |given: usize| {
+            CalculatorErrorType::FunctionArityMismatch("deriv".to_string(), given, 3)
+        };
+        assert_eq!(error("deriv(x)"), mismatch(1));
+        assert_eq!(error("deriv(x, x)"), mismatch(2));
+        assert_eq!(error("deriv(x, x, 1, 2, 3)"), mismatch(5));
+        let error = crate::eval("2 + deriv(x)").unwrap_err();
+        assert_eq!(error.token.unwrap().column, 5);
+    }
+
+    #[test]
+    fn vector_forms_with_the_wrong_number_of_arguments_are_arity_mismatches() {
+        let mismatch = |name: &str, given: usize, expected: usize| {
+            CalculatorErrorType::FunctionArityMismatch(name.to_string(), given, expected)
+        };
+        assert_eq!(error("len(1, 2)"), mismatch("len", 2, 1));
+        assert_eq!(error("len([1, 2], 3)"), mismatch("len", 2, 1));
+        assert_eq!(error("len()"), mismatch("len", 0, 1));
+        assert_eq!(error("dot([1])"), mismatch("dot", 1, 2));
+        assert_eq!(error("dot([1], [2], [3])"), mismatch("dot", 3, 2));
+        assert_eq!(
+            crate::eval("1 + len(1, 2)")
+                .unwrap_err()
+                .token
+                .unwrap()
+                .column,
+            5
+        );
+    }
+
+    #[test]
+    fn operators_the_parser_never_builds_are_type_errors() {
+        let run = |source: &str, from: &str, to: &str| {
+            let json = crate::json::to_json(&crate::parse(source).unwrap());
+            let json = json.replacen(from, to, 1);
+            let expr = crate::json::from_json(&json).unwrap();
+            Interpreter::new().interpret(&expr).unwrap_err().error
+        };
+        assert!(matches!(
+            run("1 + 2", "\"Plus\"", "\"Comma\""),
+            CalculatorErrorType::TypeError(_)
+        ));
+        assert!(matches!(
+            run("-2", "\"Minus\"", "\"Caret\""),
+            CalculatorErrorType::TypeError(_)
+        ));
+    }
+
+    #[test]
+    fn min_and_max_take_any_positive_number_of_arguments() {
+        assert_eq!(value("min(5, 2)"), 2.0);
+        assert_eq!(value("min(5, 2, 8, 1)"), 1.0);
+        assert_eq!(value("max(5, 2, 8, 1)"), 8.0);
+        assert_eq!(value("max(3)"), 3.0);
+        for name in ["min", "max"] {
+            assert_eq!(
+                error(&format!("{}()", name)),
+                CalculatorErrorType::FunctionArityMismatch(name.to_string(), 0, 1)
+            );
+        }
     }
 }
@@ -0,0 +1,532 @@
+use std::sync::Arc;
+
+use crate::{
+    errors::{CalculatorError, CalculatorErrorType},
+    parser::expressions::*,
+    scanner::{Token, TokenType},
+};
+
+/// Serializes an expression tree to JSON, keeping every token so errors in
+/// a deserialized tree still point at the original source.
+pub fn to_json(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(expr) => node(
+            "binary",
+            &[
+                ("left", to_json(&expr.left)),
+                ("operator", token(&expr.operator)),
+                ("right", to_json(&expr.right)),
+            ],
+        ),
+        Expr::Logical(expr) => node(
+            "logical",
+            &[
+                ("left", to_json(&expr.left)),
+                ("operator", token(&expr.operator)),
+                ("right", to_json(&expr.right)),
+            ],
+        ),
+        Expr::Conditional(expr) => node(
+            "conditional",
+            &[
+                ("condition", to_json(&expr.condition)),
+                ("question", token(&expr.question)),
+                ("then", to_json(&expr.then)),
+                ("otherwise", to_json(&expr.otherwise)),
+            ],
+        ),
+        Expr::Comparison(expr) => node(
+            "comparison",
+            &[
+                ("operands", array(expr.operands.iter().map(to_json))),
+                ("operators", array(expr.operators.iter().map(token))),
+            ],
+        ),
+        Expr::Grouping(expr) => node("grouping", &[("expression", to_json(&expr.expression))]),
+        Expr::List(expr) => node(
+            "list",
+            &[
+                ("bracket", token(&expr.bracket)),
+                ("elements", array(expr.elements.iter().map(to_json))),
+            ],
+        ),
+        Expr::Index(expr) => node(
+            "index",
+            &[
+                ("object", to_json(&expr.object)),
+                ("bracket", token(&expr.bracket)),
+                ("index", to_json(&expr.index)),
+            ],
+        ),
+        Expr::Literal(expr) => node("literal", &[("value", token(&expr.value))]),
+        Expr::Unary(expr) => node(
+            "unary",
+            &[
+                ("operator", token(&expr.operator)),
+                ("right", to_json(&expr.right)),
+            ],
+        ),
+        Expr::Call(expr) => node(
+            "call",
+            &[
+                ("callee", token(&expr.callee)),
+                ("paren", token(&expr.paren)),
+                ("arguments", array(expr.arguments.iter().map(to_json))),
+            ],
+        ),
+        Expr::Variable(expr) => node("variable", &[("name", token(&expr.name))]),
+        Expr::Assignment(expr) => node(
+            "assignment",
+            &[("name", token(&expr.name)), ("value", to_json(&expr.value))],
+        ),
+        Expr::FunctionDefinition(expr) => node(
+            "function_definition",
+            &[
+                ("name", token(&expr.name)),
+                ("parameters", array(expr.parameters.iter().map(token))),
+                ("body", to_json(&expr.body)),
+            ],
+        ),
+    }
+}
+
+/// Rebuilds an expression tree from the output of `to_json`.
+pub fn from_json(json: &str) -> Result<Expr, CalculatorError> {
+    let mut reader = Reader {
+        source: json.chars().collect(),
+        current: 0,
+    };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.current < reader.source.len() {
+        return Err(error("Unexpected characters after the JSON value."));
+    }
+    expression(&value)
+}
+
+/// Quotes and escapes `value` as a JSON string.
+pub fn string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// JSON has no representation for infinities or NaN, so those are written
+/// as strings.
+pub fn number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        string(&value.to_string())
+    }
+}
+
+fn token(token: &Token) -> String {
+    format!(
+        "{{\"kind\": {}, \"lexeme\": {}, \"literal\": {}, \"line\": {}, \"column\": {}}}",
+        string(&format!("{:?}", token.kind)),
+        string(&token.lexeme),
+        token.literal.map_or("null".to_string(), number),
+        token.line,
+        token.column
+    )
+}
+
+/// Builds the JSON object for a node from its type and already serialized
+/// fields.
+fn node(kind: &str, fields: &[(&str, String)]) -> String {
+    let mut json = format!("{{\"type\": {}", string(kind));
+    for (name, value) in fields {
+        json.push_str(&format!(", {}: {}", string(name), value));
+    }
+    json.push('}');
+    json
+}
+
+fn array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
+
+fn error(message: &str) -> CalculatorError {
+    CalculatorError {
+        error: CalculatorErrorType::SyntaxError(message.to_string()),
+        token: None,
+    }
+}
+
+enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn field(&self, name: &str) -> Result<&Json, CalculatorError> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| error(&format!("Missing field '{}'.", name))),
+            _ => Err(error("Expected an object.")),
+        }
+    }
+
+    fn as_str(&self) -> Result<&str, CalculatorError> {
+        match self {
+            Json::String(string) => Ok(string),
+            _ => Err(error("Expected a string.")),
+        }
+    }
+
+    fn as_usize(&self) -> Result<usize, CalculatorError> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Ok(*number as usize),
+            _ => Err(error("Expected a non-negative integer.")),
+        }
+    }
+
+    fn as_array(&self) -> Result<&[Json], CalculatorError> {
+        match self {
+            Json::Array(values) => Ok(values),
+            _ => Err(error("Expected an array.")),
+        }
+    }
+}
+
+fn expression(json: &Json) -> Result<Expr, CalculatorError> {
+    let child = |name| expression(json.field(name)?).map(Box::new);
+    let token = |name| token_from(json.field(name)?);
+    Ok(match json.field("type")?.as_str()? {
+        "binary" => Expr::Binary(Binary {
+            left: child("left")?,
+            operator: token("operator")?,
+            right: child("right")?,
+        }),
+        "logical" => Expr::Logical(Logical {
+            left: child("left")?,
+            operator: token("operator")?,
+            right: child("right")?,
+        }),
+        "conditional" => Expr::Conditional(Conditional {
+            condition: child("condition")?,
+            question: token("question")?,
+            then: child("then")?,
+            otherwise: child("otherwise")?,
+        }),
+        "comparison" => Expr::Comparison(Comparison {
+            operands: json
+                .field("operands")?
+                .as_array()?
+                .iter()
+                .map(expression)
+                .collect::<Result<_, _>>()?,
+            operators: json
+                .field("operators")?
+                .as_array()?
+                .iter()
+                .map(token_from)
+                .collect::<Result<_, _>>()?,
+        }),
+        "grouping" => Expr::Grouping(Grouping {
+            expression: child("expression")?,
+        }),
+        "list" => Expr::List(List {
+            bracket: token("bracket")?,
+            elements: json
+                .field("elements")?
+                .as_array()?
+                .iter()
+                .map(expression)
+                .collect::<Result<_, _>>()?,
+        }),
+        "index" => Expr::Index(Index {
+            object: child("object")?,
+            bracket: token("bracket")?,
+            index: child("index")?,
+        }),
+        "literal" => Expr::Literal(Literal {
+            value: token("value")?,
+        }),
+        "unary" => Expr::Unary(Unary {
+            operator: token("operator")?,
+            right: child("right")?,
+        }),
+        "call" => Expr::Call(Call {
+            callee: token("callee")?,
+            paren: token("paren")?,
+            arguments: json
+                .field("arguments")?
+                .as_array()?
+                .iter()
+                .map(expression)
+                .collect::<Result<_, _>>()?,
+        }),
+        "variable" => Expr::Variable(Variable {
+            name: token("name")?,
+        }),
+        "assignment" => Expr::Assignment(Assignment {
+            name: token("name")?,
+            value: child("value")?,
+        }),
+        "function_definition" => Expr::FunctionDefinition(FunctionDefinition {
+            name: token("name")?,
+            parameters: json
+                .field("parameters")?
+                .as_array()?
+                .iter()
+                .map(token_from)
+                .collect::<Result<_, _>>()?,
+            body: Arc::from(child("body")?),
+        }),
+        kind => return Err(error(&format!("Unknown expression type '{}'.", kind))),
+    })
+}
+
+fn token_from(json: &Json) -> Result<Token, CalculatorError> {
+    let literal = match json.field("literal")? {
+        Json::Null => None,
+        Json::Number(number) => Some(*number),
+        Json::String(number) => Some(
+            number
+                .parse()
+                .map_err(|_| error("Expected a number literal."))?,
+        ),
+        _ => return Err(error("Expected a number literal.")),
+    };
+    Ok(Token {
+        kind: token_kind(json.field("kind")?.as_str()?)?,
+        lexeme: json.field("lexeme")?.as_str()?.to_string(),
+        literal,
+        line: json.field("line")?.as_usize()?,
+        column: json.field("column")?.as_usize()?,
+    })
+}
+
+fn token_kind(name: &str) -> Result<TokenType, CalculatorError> {
+    Ok(match name {
+        "LeftParen" => TokenType::LeftParen,
+        "RightParen" => TokenType::RightParen,
+        "LeftBracket" => TokenType::LeftBracket,
+        "RightBracket" => TokenType::RightBracket,
+        "Plus" => TokenType::Plus,
+        "Minus" => TokenType::Minus,
+        "Star" => TokenType::Star,
+        "Slash" => TokenType::Slash,
+        "Modulo" => TokenType::Modulo,
+        "Caret" => TokenType::Caret,
+        "Bang" => TokenType::Bang,
+        "Bar" => TokenType::Bar,
+        "Equal" => TokenType::Equal,
+        "Less" => TokenType::Less,
+        "Greater" => TokenType::Greater,
+        "LessEqual" => TokenType::LessEqual,
+        "GreaterEqual" => TokenType::GreaterEqual,
+        "EqualEqual" => TokenType::EqualEqual,
+        "BangEqual" => TokenType::BangEqual,
+        "And" => TokenType::And,
+        "Or" => TokenType::Or,
+        "Not" => TokenType::Not,
+        "True" => TokenType::True,
+        "False" => TokenType::False,
+        "Question" => TokenType::Question,
+        "Colon" => TokenType::Colon,
+        "Comma" => TokenType::Comma,
+        "Semicolon" => TokenType::Semicolon,
+        "Dot" => TokenType::Dot,
+        "Root" => TokenType::Root,
+        "Superscript" => TokenType::Superscript,
+        "Identifier" => TokenType::Identifier,
+        "Number" => TokenType::Number,
+        "Eof" => TokenType::Eof,
+        kind => return Err(error(&format!("Unknown token kind '{}'.", kind))),
+    })
+}
+
+/// A minimal JSON reader, enough for the output of `to_json`.
+struct Reader {
+    source: Vec<char>,
+    current: usize,
+}
+
+impl Reader {
+    fn value(&mut self) -> Result<Json, CalculatorError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(error("Expected a JSON value.")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, CalculatorError> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.current += 1;
+            } else {
+                self.expect('}')?;
+                return Ok(Json::Object(fields));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, CalculatorError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.current += 1;
+            } else {
+                self.expect(']')?;
+                return Ok(Json::Array(values));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CalculatorError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('u') => {
+                        let digits: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| error("Invalid unicode escape."))?;
+                        string.push(c);
+                    }
+                    Some(c) => string.push(c),
+                    None => return Err(error("Unterminated string.")),
+                },
+                Some(c) => string.push(c),
+                None => return Err(error("Unterminated string.")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, CalculatorError> {
+        let start = self.current;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.current += 1;
+        }
+        let number: String = self.source[start..self.current].iter().collect();
+        number
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| error("Invalid number."))
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, CalculatorError> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), CalculatorError> {
+        if self.next() == Some(expected) {
+            Ok(())
+        } else {
+            Err(error(&format!("Expected '{}'.", expected)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source.get(self.current).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_of_expression_survives_a_round_trip() {
+        for source in [
+            "sin(x) + max(1, 2, 3) * pi",
+            "f(x, y) = x^2 + y",
+            "g() = 1",
+            "[1, 2.5, 1e300][i + 1]",
+            "f(x)[0][1]",
+            "v = [0.1, -0.2] * 3",
+            "1 < x <= 2 and not (y == 3 or z != 4)",
+            "x > 0 ? √x : |x|!",
+            "(50%) + 2π²",
+            "true == false",
+        ] {
+            let expr = crate::parse(source).unwrap();
+            let json = to_json(&expr);
+            assert!(from_json(&json).unwrap() == expr, "{}\n{}", source, json);
+        }
+    }
+
+    #[test]
+    fn malformed_json_is_a_syntax_error() {
+        for json in [
+            "",
+            "{",
+            "{\"type\": \"nonsense\"}",
+            "[1, 2]",
+            "{\"type\": \"literal\"} x",
+        ] {
+            let error = from_json(json).unwrap_err();
+            assert!(
+                matches!(error.error, CalculatorErrorType::SyntaxError(_)),
+                "{}",
+                json
+            );
+        }
+    }
+}
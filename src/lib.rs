@@ -0,0 +1,95 @@
+#[cfg(feature = "bignum")]
+pub mod bignum;
+pub mod errors;
+pub mod format;
+pub mod interpreter;
+pub mod json;
+pub mod optimizer;
+pub mod parser;
+pub mod scanner;
+pub mod scope;
+pub mod value;
+pub mod wasm;
+
+use std::{cell::RefCell, collections::HashMap};
+
+use errors::CalculatorError;
+use parser::expressions::Expr;
+use scanner::Token;
+use scope::Scope;
+use value::Value;
+
+/// Scans and parses `source` without evaluating it, for tools that work on
+/// the syntax tree.
+pub fn parse(source: &str) -> Result<Expr, CalculatorError> {
+    let mut scanner = scanner::Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let mut parser = parser::Parser::new(tokens);
+    // Only the first syntax error is reported through this interface.
+    parser.parse().map_err(|mut errors| errors.remove(0))
+}
+
+/// Scans, parses and evaluates `source` with a fresh interpreter holding the
+/// built-in constants and functions.
+pub fn eval(source: &str) -> Result<Value, CalculatorError> {
+    let expr = parse(source)?;
+    let mut interpreter = interpreter::Interpreter::new();
+    interpreter.interpret(&expr)
+}
+
+/// Like `eval`, with `variables` filling in names the expression uses, as
+/// in `eval_with("a * b + c", &variables)`. Built-in names take precedence,
+/// so an entry named `pi` or `sin` is ignored.
+pub fn eval_with(source: &str, variables: &HashMap<String, f64>) -> Result<Value, CalculatorError> {
+    let expr = parse(source)?;
+    let mut interpreter = interpreter::Interpreter::new();
+    for (name, value) in variables {
+        if interpreter.scope.get(name).is_none() && interpreter.arity(name).is_none() {
+            interpreter.scope.define(name, (*value).into());
+        }
+    }
+    interpreter.interpret(&expr)
+}
+
+/// An expression parsed once so it can be evaluated many times, such as
+/// when plotting `sin(x) + x^2` over thousands of values of `x`.
+pub struct Compiled {
+    expr: Expr,
+    /// The first token of the source, where type errors in the result are
+    /// reported.
+    start: Token,
+    interpreter: RefCell<interpreter::Interpreter>,
+}
+
+impl Compiled {
+    /// Scans, parses and constant-folds `source`.
+    pub fn new(source: &str) -> Result<Compiled, CalculatorError> {
+        let mut scanner = scanner::Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens()?;
+        let start = tokens[0].clone();
+        let mut parser = parser::Parser::new(tokens);
+        let expr = parser.parse().map_err(|mut errors| errors.remove(0))?;
+        let interpreter = interpreter::Interpreter::new();
+        Ok(Compiled {
+            expr: optimizer::optimize(expr, interpreter.epsilon),
+            start,
+            interpreter: RefCell::new(interpreter),
+        })
+    }
+
+    /// Evaluates the expression with `vars` bound on top of the built-in
+    /// constants. Assignments made by the expression don't outlive the call.
+    pub fn eval(&self, vars: &HashMap<String, f64>) -> Result<f64, CalculatorError> {
+        let mut interpreter = self.interpreter.borrow_mut();
+        let mut scope = Scope::with_parent(std::mem::take(&mut interpreter.scope));
+        for (name, value) in vars {
+            scope.define(name, (*value).into());
+        }
+        interpreter.scope = scope;
+        let result = interpreter.interpret(&self.expr);
+        interpreter.scope = std::mem::take(&mut interpreter.scope)
+            .into_parent()
+            .expect("the variables scope always has a parent");
+        result?.to_number(&self.start)
+    }
+}
@@ -1,59 +1,808 @@
-use std::io::Write;
+#![cfg_attr(target_arch = "wasm32", allow(dead_code, unused_imports))]
 
-pub mod scanner;
-pub mod parser;
-mod errors;
-mod interpreter;
+use std::{
+    io::{IsTerminal, Write},
+    time::{Duration, Instant},
+};
 
+use calculator::{
+    errors,
+    format::{self, Base, FormatOptions, NotationMode},
+    interpreter, json, parser, scanner,
+    value::Value,
+};
+
+/// Command-line options.
+#[derive(Default)]
+struct Options {
+    debug: bool,
+    time: bool,
+    json: bool,
+    file: Option<String>,
+    expression: Option<String>,
+    format: FormatOptions,
+    epsilon: Option<f64>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--debug" => options.debug = true,
+                "--time" => options.time = true,
+                "--json" => options.json = true,
+                "--file" => {
+                    options.file = Some(args.next().ok_or("Expected a path after '--file'.")?)
+                }
+                "--precision" => {
+                    let precision = args
+                        .next()
+                        .ok_or("Expected a number after '--precision'.")?;
+                    options.format.precision = Some(parse_precision(&precision)?);
+                }
+                "--base" => {
+                    let base = args.next().ok_or("Expected a base after '--base'.")?;
+                    options.format.base = parse_base(&base)?;
+                }
+                "--notation" => {
+                    let notation = args
+                        .next()
+                        .ok_or("Expected a notation after '--notation'.")?;
+                    options.format.notation = parse_notation(&notation)?;
+                }
+                "--epsilon" => {
+                    let epsilon = args.next().ok_or("Expected a number after '--epsilon'.")?;
+                    options.epsilon = Some(parse_epsilon(&epsilon)?);
+                }
+                _ => options.expression = Some(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// An interpreter with the settings given on the command line.
+    fn interpreter(&self) -> interpreter::Interpreter {
+        let mut interpreter = interpreter::Interpreter::new();
+        if let Some(epsilon) = self.epsilon {
+            interpreter.epsilon = epsilon;
+        }
+        interpreter
+    }
+}
+
+fn parse_base(base: &str) -> Result<Base, String> {
+    Base::from_name(base).ok_or_else(|| {
+        format!(
+            "Unknown base '{}'. Expected one of dec, hex, bin or oct.",
+            base
+        )
+    })
+}
+
+fn parse_notation(notation: &str) -> Result<NotationMode, String> {
+    NotationMode::from_name(notation).ok_or_else(|| {
+        format!(
+            "Unknown notation '{}'. Expected one of auto, fixed, sci or eng.",
+            notation
+        )
+    })
+}
+
+fn parse_epsilon(epsilon: &str) -> Result<f64, String> {
+    match epsilon.parse::<f64>() {
+        Ok(epsilon) if epsilon >= 0.0 => Ok(epsilon),
+        _ => Err(format!("Invalid epsilon '{}'.", epsilon)),
+    }
+}
+
+fn parse_precision(precision: &str) -> Result<usize, String> {
+    precision
+        .parse()
+        .map_err(|_| format!("Invalid precision '{}'.", precision))
+}
+
+// The command line and REPL need a terminal and a file system, so the
+// browser build only uses the library's `wasm` module.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
-    let debug = std::env::args().any(|arg| arg == "--debug");
-    repl(debug);
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(2);
+        }
+    };
+    let interpreter = options.interpreter();
+    if let Some(path) = &options.file {
+        evaluate_file(
+            path,
+            interpreter,
+            options.debug,
+            options.time,
+            &options.format,
+        );
+    } else if let Some(expression) = &options.expression {
+        evaluate_once(
+            expression,
+            interpreter,
+            options.debug,
+            options.time,
+            options.json,
+            &options.format,
+        );
+    } else if std::io::stdin().is_terminal() {
+        repl(interpreter, options.debug, options.time, options.format);
+    } else {
+        // Piped input is evaluated like a file, without the REPL's banner
+        // and prompts getting mixed into the output.
+        let lines = std::io::stdin().lines().map_while(Result::ok);
+        evaluate_lines(
+            lines,
+            interpreter,
+            options.debug,
+            options.time,
+            &options.format,
+        );
+    }
+}
+
+/// Evaluates each line of a file with a shared interpreter, so assignments
+/// carry forward. Blank lines and comment lines are skipped, and an error on
+/// one line is reported without stopping the rest of the file.
+fn evaluate_file(
+    path: &str,
+    interpreter: interpreter::Interpreter,
+    debug: bool,
+    time: bool,
+    format: &FormatOptions,
+) {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Error: Could not read '{}': {}", path, error);
+            std::process::exit(1);
+        }
+    };
+    evaluate_lines(
+        contents.lines().map(str::to_string),
+        interpreter,
+        debug,
+        time,
+        format,
+    );
+}
+
+/// Evaluates each line with a shared interpreter as described for
+/// `evaluate_file`, exiting with a non-zero code if any line failed.
+fn evaluate_lines(
+    lines: impl Iterator<Item = String>,
+    mut interpreter: interpreter::Interpreter,
+    debug: bool,
+    time: bool,
+    format: &FormatOptions,
+) {
+    let mut failed = false;
+    for (index, line) in lines.enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let (result, timings) = evaluate_source(line, &mut interpreter, debug, time);
+        match result {
+            Ok(Value::Unit) => (),
+            Ok(result) => {
+                println!("{}", format_output(line, &result, format));
+                interpreter.record_result(result);
+            }
+            Err(errors) => {
+                failed = true;
+                for error in errors {
+                    eprintln!("Error on line {}: {}", index + 1, error.render(line));
+                }
+            }
+        }
+        // Timings go to stderr so the results can still be piped elsewhere.
+        if let Some(timings) = timings {
+            eprintln!("Time: {}", timings);
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
 }
 
-fn calculate(source: String) -> Result<f64, errors::CalculatorError> {
+/// Evaluates a single expression given on the command line, printing the
+/// result to stdout or the error to stderr with a non-zero exit code.
+fn evaluate_once(
+    source: &str,
+    mut interpreter: interpreter::Interpreter,
+    debug: bool,
+    time: bool,
+    json: bool,
+    format: &FormatOptions,
+) {
+    let (result, timings) = evaluate_source(source, &mut interpreter, debug, time);
+    if let Some(timings) = timings {
+        eprintln!("Time: {}", timings);
+    }
+    match result {
+        Ok(Value::Unit) => (),
+        Ok(result) if json => println!("{{\"result\": {}}}", json_value(&result, format)),
+        Ok(result) => println!("{}", format_output(source, &result, format)),
+        Err(errors) => {
+            for error in errors {
+                if json {
+                    eprintln!("{{\"error\": {}}}", json::string(&error.to_string()));
+                } else {
+                    eprintln!("Error: {}", error.render(source));
+                }
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Formats the result of evaluating `source`. With the `bignum` feature,
+/// integers too large for an `f64` to hold exactly, such as `2^200` or `25!`,
+/// are recomputed exactly and printed in full.
+#[cfg_attr(not(feature = "bignum"), allow(unused_variables))]
+fn format_output(source: &str, result: &Value, format: &FormatOptions) -> String {
+    #[cfg(feature = "bignum")]
+    if let Value::Number(number) = *result {
+        // Overflow shows up as infinity, or NaN once two infinities meet.
+        let inexact =
+            !number.is_finite() || (number.fract() == 0.0 && number.abs() >= 2f64.powi(53));
+        let exponent = matches!(
+            format.notation,
+            NotationMode::Scientific | NotationMode::Engineering
+        );
+        if inexact && format.precision.is_none() && format.base == Base::Decimal && !exponent {
+            let exact = scanner::Scanner::new(source.to_string())
+                .scan_tokens()
+                .ok()
+                .and_then(|tokens| parser::Parser::new(tokens).program().ok())
+                .and_then(|program| program.last().and_then(calculator::bignum::exact));
+            if let Some(exact) = exact {
+                return exact.to_string();
+            }
+        }
+    }
+    format::format_value(result, format)
+}
+
+/// JSON has no representation for infinities or NaN, so those are written
+/// as strings. Vectors become arrays.
+fn json_value(value: &Value, format: &FormatOptions) -> String {
+    match value {
+        Value::Number(number) if !number.is_finite() => json::number(*number),
+        Value::Vector(vector) => {
+            let elements: Vec<_> = vector
+                .iter()
+                .map(|element| json_value(&Value::Number(*element), format))
+                .collect();
+            format!("[{}]", elements.join(", "))
+        }
+        // JSON numbers can only be written in decimal.
+        value => {
+            let format = FormatOptions {
+                base: Base::Decimal,
+                ..format.clone()
+            };
+            format::format_value(value, &format)
+        }
+    }
+}
+
+/// How long each phase of evaluating one input took. Phases after a failed
+/// one are zero.
+#[derive(Default)]
+struct Timings {
+    scan: Duration,
+    parse: Duration,
+    interpret: Duration,
+}
+
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "scan {}µs, parse {}µs, interpret {}µs",
+            self.scan.as_micros(),
+            self.parse.as_micros(),
+            self.interpret.as_micros()
+        )
+    }
+}
+
+/// Evaluates `source` with `calculate`, `calculate_with_debug` or, when
+/// timing, `calculate_timed`, returning the timings only in the last case.
+/// Timing with `debug` on prints the debug output too.
+fn evaluate_source(
+    source: &str,
+    interpreter: &mut interpreter::Interpreter,
+    debug: bool,
+    time: bool,
+) -> (Result<Value, Vec<errors::CalculatorError>>, Option<Timings>) {
+    if time {
+        let mut timings = Timings::default();
+        let result = calculate_timed(source.to_string(), interpreter, debug, &mut timings);
+        (result, Some(timings))
+    } else if debug {
+        (calculate_with_debug(source.to_string(), interpreter), None)
+    } else {
+        (calculate(source.to_string(), interpreter), None)
+    }
+}
+
+/// Like `calculate`, recording how long scanning, parsing and interpreting
+/// took in `timings`. With `debug`, the tokens and syntax trees are printed
+/// like `calculate_with_debug` does, outside the timed phases.
+fn calculate_timed(
+    source: String,
+    interpreter: &mut interpreter::Interpreter,
+    debug: bool,
+    timings: &mut Timings,
+) -> Result<Value, Vec<errors::CalculatorError>> {
+    let start = Instant::now();
+    let mut scanner = scanner::Scanner::new(source);
+    let tokens = scanner.scan_tokens().map_err(|error| vec![error]);
+    timings.scan = start.elapsed();
+    let tokens = tokens?;
+    if debug {
+        println!("Tokens: {:?}", tokens);
+    }
+    let start = Instant::now();
+    let mut parser = parser::Parser::new(tokens);
+    let program = parser.program();
+    timings.parse = start.elapsed();
+    let program = program?;
+    if debug {
+        for expr in &program {
+            println!("AST: {:?}", expr);
+        }
+    }
+    let start = Instant::now();
+    let result = interpreter
+        .interpret_program(&program)
+        .map_err(|error| vec![error]);
+    timings.interpret = start.elapsed();
+    result
+}
+
+/// Evaluates `source`, returning every syntax error if it fails to parse.
+/// A line may hold several expressions separated by semicolons, in which
+/// case only the last one's value is returned.
+fn calculate(
+    source: String,
+    interpreter: &mut interpreter::Interpreter,
+) -> Result<Value, Vec<errors::CalculatorError>> {
     let mut scanner = scanner::Scanner::new(source);
-    let tokens = scanner.scan_tokens();
+    let tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
     let mut parser = parser::Parser::new(tokens);
-    let expr = parser.parse()?;
-    let mut interpreter = interpreter::Interpreter::new();
-    let result = interpreter.interpret(&*expr)?;
+    let program = parser.program()?;
+    let result = interpreter
+        .interpret_program(&program)
+        .map_err(|error| vec![error])?;
     Ok(result)
 }
 
-fn calculate_with_debug(source: String) -> Result<f64, errors::CalculatorError> {
+fn calculate_with_debug(
+    source: String,
+    interpreter: &mut interpreter::Interpreter,
+) -> Result<Value, Vec<errors::CalculatorError>> {
     let mut scanner = scanner::Scanner::new(source);
-    let tokens = scanner.scan_tokens();
+    let tokens = scanner.scan_tokens().map_err(|error| vec![error])?;
     println!("Tokens: {:?}", tokens);
     let mut parser = parser::Parser::new(tokens);
-    let expr = parser.parse()?;
-    println!("AST: {:?}", expr);
-    let mut interpreter = interpreter::Interpreter::new();
-    let result = interpreter.interpret(&*expr)?;
+    let program = parser.program()?;
+    for expr in &program {
+        println!("AST: {:?}", expr);
+    }
+    let result = interpreter
+        .interpret_program(&program)
+        .map_err(|error| vec![error])?;
     Ok(result)
 }
 
-fn repl(debug: bool) {
+fn repl(
+    mut interpreter: interpreter::Interpreter,
+    debug: bool,
+    mut time: bool,
+    mut format: FormatOptions,
+) {
     // prompt console
     let debug_text = if debug { " (debug mode)" } else { "" };
     println!("Welcome to the calculator!{debug_text}");
     println!("Enter an expression to evaluate it, or 'exit' to quit.");
+    // The interpreter is kept across lines so assigned variables persist.
+    let mut history = History::load();
     loop {
         print!("> ");
-        std::io::stdout().flush().unwrap();
+        // A closed stdout shouldn't bring the REPL down just for the prompt.
+        let _ = std::io::stdout().flush();
         let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
+        // Reading 0 bytes means stdin hit EOF (e.g. Ctrl-D or the end of a
+        // pipe), which is treated like `exit`.
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
         let input = input.trim();
         if input == "exit" {
             break;
         }
-        let result = if debug {
-            calculate_with_debug(input.to_string())
-        } else {
-            calculate(input.to_string())
-        };
+        if input.is_empty() {
+            continue;
+        }
+        if input == ":history" {
+            history.print();
+            continue;
+        }
+        history.push(input);
+        if input == ":time" {
+            time = !time;
+            println!("Timing {}.", if time { "on" } else { "off" });
+            continue;
+        }
+        if let Some(command) = input.strip_prefix(':') {
+            run_command(command, &mut interpreter, &mut format);
+            continue;
+        }
+        let (result, timings) = evaluate_source(input, &mut interpreter, debug, time);
         match result {
-            Ok(result) => println!("Result: {}", result),
+            Ok(Value::Unit) => (),
+            Ok(result) => {
+                println!("Result: {}", format_output(input, &result, &format));
+                interpreter.record_result(result);
+            }
+            Err(errors) => {
+                for error in errors {
+                    println!("Error: {}", error.render(input));
+                }
+            }
+        }
+        if let Some(timings) = timings {
+            println!("Time: {}", timings);
+        }
+    }
+}
+
+/// Runs a REPL command such as `:mode degrees`. Commands are prefixed with
+/// `:` so they can never be mistaken for an expression.
+fn run_command(
+    command: &str,
+    interpreter: &mut interpreter::Interpreter,
+    format: &mut FormatOptions,
+) {
+    if let Some(source) = command.strip_prefix("ast ") {
+        print_ast(source);
+        return;
+    }
+    if let Some(path) = command.strip_prefix("save ") {
+        save_session(path.trim(), interpreter);
+        return;
+    }
+    if let Some(path) = command.strip_prefix("load ") {
+        load_session(path.trim(), interpreter);
+        return;
+    }
+    let mut words = command.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("mode"), Some("radians")) => {
+            interpreter.angle_mode = interpreter::AngleMode::Radians;
+            println!("Angle mode set to radians.");
+        }
+        (Some("mode"), Some("degrees")) => {
+            interpreter.angle_mode = interpreter::AngleMode::Degrees;
+            println!("Angle mode set to degrees.");
+        }
+        (Some("help"), None) => print_help(interpreter),
+        (Some("help"), Some(name)) => match interpreter.descriptions.get(name) {
+            Some(description) => println!("{}", description),
+            None if interpreter.arity(name).is_some() => {
+                println!("{}: a function with no description.", name)
+            }
+            None => println!("Error: Nothing named '{}' to describe.", name),
+        },
+        (Some("clear"), None) => {
+            // The angle mode and epsilon are settings like the precision, so
+            // they survive.
+            let angle_mode = interpreter.angle_mode;
+            let epsilon = interpreter.epsilon;
+            *interpreter = interpreter::Interpreter::new();
+            interpreter.angle_mode = angle_mode;
+            interpreter.epsilon = epsilon;
+            println!("Cleared all variables and functions.");
+        }
+        (Some("clear"), Some(name)) => {
+            if interpreter.forget(name) {
+                println!("Cleared '{}'.", name);
+            } else {
+                println!("Error: No user-defined variable or function '{}'.", name);
+            }
+        }
+        (Some("vars"), None) => {
+            let mut variables: Vec<_> = interpreter.scope.variables.iter().collect();
+            variables.sort_by_key(|(name, _)| *name);
+            for (name, value) in variables {
+                println!("{} = {}", name, format::format_value(value, format));
+            }
+        }
+        (Some("funcs"), None) => {
+            for name in interpreter.function_names() {
+                println!("{}", name);
+            }
+        }
+        (Some("precision"), Some("default")) => {
+            format.precision = None;
+            println!("Precision reset to the default.");
+        }
+        (Some("precision"), Some(precision)) => match parse_precision(precision) {
+            Ok(precision) => {
+                format.precision = Some(precision);
+                println!("Precision set to {} decimal places.", precision);
+            }
+            Err(error) => println!("Error: {}", error),
+        },
+        (Some("precision"), None) => match format.precision {
+            Some(precision) => println!("Precision: {} decimal places", precision),
+            None => println!("Precision: default"),
+        },
+        (Some("base"), Some(base)) => match parse_base(base) {
+            Ok(base) => {
+                format.base = base;
+                println!("Output base set to {:?}.", base);
+            }
+            Err(error) => println!("Error: {}", error),
+        },
+        (Some("base"), None) => println!("Output base: {:?}", format.base),
+        (Some("notation"), Some(notation)) => match parse_notation(notation) {
+            Ok(notation) => {
+                format.notation = notation;
+                println!("Notation set to {:?}.", notation);
+            }
             Err(error) => println!("Error: {}", error),
+        },
+        (Some("notation"), None) => println!("Notation: {:?}", format.notation),
+        (Some("epsilon"), Some("default")) => {
+            interpreter.epsilon = interpreter::DEFAULT_EPSILON;
+            println!("Epsilon reset to {:e}.", interpreter::DEFAULT_EPSILON);
+        }
+        (Some("epsilon"), Some(epsilon)) => match parse_epsilon(epsilon) {
+            Ok(epsilon) => {
+                interpreter.epsilon = epsilon;
+                if epsilon == 0.0 {
+                    println!("Numbers are now compared exactly.");
+                } else {
+                    println!("Epsilon set to {:e}.", epsilon);
+                }
+            }
+            Err(error) => println!("Error: {}", error),
+        },
+        (Some("epsilon"), None) => println!("Epsilon: {:e}", interpreter.epsilon),
+        (Some("seed"), Some(seed)) => match seed.parse() {
+            Ok(seed) => {
+                interpreter.seed(seed);
+                println!("Random seed set to {}.", seed);
+            }
+            Err(_) => println!("Error: Invalid seed '{}'.", seed),
+        },
+        (Some("mode"), None) => println!("Angle mode: {:?}", interpreter.angle_mode),
+        (Some("mode"), Some(_)) => println!("Error: Expected ':mode radians' or ':mode degrees'."),
+        _ => println!("Error: Unknown command ':{}'.", command),
+    }
+}
+
+/// Prints the built-in constants, every function grouped by how many
+/// arguments it takes, and the REPL commands.
+fn print_help(interpreter: &interpreter::Interpreter) {
+    use interpreter::Arity;
+    // The smallest argument count, so groups print in a sensible order.
+    fn count(arity: &Arity) -> usize {
+        match arity {
+            Arity::Single | Arity::Variadic => 1,
+            Arity::Double => 2,
+            Arity::Triple => 3,
+            Arity::Fixed(count) => *count,
+            Arity::Overloaded(arities) => arities.iter().map(count).min().unwrap_or(0),
+        }
+    }
+    fn label(arity: &Arity) -> String {
+        match arity {
+            Arity::Single => "1 argument".to_string(),
+            Arity::Variadic => "1 or more arguments".to_string(),
+            Arity::Overloaded(arities) => {
+                let counts: Vec<_> = arities
+                    .iter()
+                    .map(|arity| count(arity).to_string())
+                    .collect();
+                format!("{} arguments", counts.join(" or "))
+            }
+            arity => format!("{} arguments", count(arity)),
+        }
+    }
+
+    println!("Constants: {}", interpreter.constant_names().join(", "));
+    let mut groups: std::collections::BTreeMap<_, Vec<_>> = Default::default();
+    for name in interpreter.function_names() {
+        if let Some(arity) = interpreter.arity(&name) {
+            groups
+                .entry((count(&arity), label(&arity)))
+                .or_default()
+                .push(name);
+        }
+    }
+    println!("Functions:");
+    for ((_, label), names) in groups {
+        println!("  {}: {}", label, names.join(", "));
+    }
+    println!("Commands:");
+    let commands = [
+        (
+            ":help [name]",
+            "Show this list, or describe a constant or function.",
+        ),
+        (":vars", "List every variable."),
+        (
+            ":clear [name]",
+            "Forget every variable and function, or just one.",
+        ),
+        (":funcs", "List every function."),
+        (":mode [radians|degrees]", "Show or set the angle mode."),
+        (
+            ":precision [N|default]",
+            "Show or set the decimal places shown.",
+        ),
+        (":base [dec|hex|bin|oct]", "Show or set the output base."),
+        (
+            ":notation [auto|fixed|sci|eng]",
+            "Show or set how decimals are laid out.",
+        ),
+        (
+            ":epsilon [N|default]",
+            "Show or set the comparison tolerance, 0 for exact.",
+        ),
+        (":seed N", "Make random numbers reproducible."),
+        (":ast <expr>", "Show how an expression is parsed."),
+        (
+            ":save <path>",
+            "Save your variables and functions to a file.",
+        ),
+        (":load <path>", "Evaluate every line of a file."),
+        (":history", "Show previous input."),
+        (":time", "Toggle showing how long each input took."),
+        ("exit", "Quit."),
+    ];
+    for (command, description) in commands {
+        println!("  {:<32}{}", command, description);
+    }
+}
+
+/// Writes the user's variables and functions to `path`, one definition per
+/// line, so `:load` or `--file` can recreate them.
+fn save_session(path: &str, interpreter: &interpreter::Interpreter) {
+    let definitions = interpreter.definitions();
+    let mut contents = definitions.join("\n");
+    contents.push('\n');
+    match std::fs::write(path, contents) {
+        Ok(()) => println!("Saved {} definitions to '{}'.", definitions.len(), path),
+        Err(error) => println!("Error: Could not write '{}': {}", path, error),
+    }
+}
+
+/// Evaluates each line of `path` in the current session, skipping blank
+/// lines and comments like `--file` does. Lines that fail are reported and
+/// the rest still run.
+fn load_session(path: &str, interpreter: &mut interpreter::Interpreter) {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("Error: Could not read '{}': {}", path, error);
+            return;
+        }
+    };
+    let mut loaded = 0;
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        match calculate(line.to_string(), interpreter) {
+            Ok(_) => loaded += 1,
+            Err(errors) => {
+                for error in errors {
+                    println!("Error on line {}: {}", index + 1, error.render(line));
+                }
+            }
+        }
+    }
+    println!("Loaded {} lines from '{}'.", loaded, path);
+}
+
+/// Prints the fully parenthesized form of `source` without evaluating it.
+fn print_ast(source: &str) {
+    let mut scanner = scanner::Scanner::new(source.to_string());
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(error) => {
+            println!("Error: {}", error.render(source));
+            return;
+        }
+    };
+    let mut parser = parser::Parser::new(tokens);
+    match parser.parse() {
+        Ok(expr) => println!("{}", expr.display()),
+        Err(errors) => {
+            for error in errors {
+                println!("Error: {}", error.render(source));
+            }
+        }
+    }
+}
+
+/// The most lines kept in `~/.calculator_history`; older ones are dropped.
+const MAX_HISTORY: usize = 1000;
+
+/// The lines entered in the REPL, persisted to `~/.calculator_history` so
+/// they survive across sessions.
+struct History {
+    entries: Vec<String>,
+    path: Option<std::path::PathBuf>,
+}
+
+impl History {
+    fn load() -> History {
+        let path = std::env::var_os("HOME")
+            .map(|home| std::path::Path::new(&home).join(".calculator_history"));
+        let entries = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        let mut history = History { entries, path };
+        let excess = history.entries.len().saturating_sub(MAX_HISTORY);
+        if excess > 0 {
+            history.entries.drain(..excess);
+            history.save();
+        }
+        history
+    }
+
+    fn push(&mut self, line: &str) {
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.remove(0);
+            self.save();
+            return;
+        }
+        // Failing to save history shouldn't interrupt the session.
+        if let Some(path) = &self.path {
+            if let Ok(mut file) = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+            {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Rewrites the history file with the entries kept in memory.
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            let mut contents = self.entries.join("\n");
+            contents.push('\n');
+            let _ = std::fs::write(path, contents);
+        }
+    }
+
+    fn print(&self) {
+        for (index, entry) in self.entries.iter().enumerate() {
+            println!("{:>4}  {}", index + 1, entry);
         }
     }
 }
@@ -0,0 +1,229 @@
+use crate::{
+    interpreter::Interpreter,
+    parser::expressions::*,
+    scanner::{Token, TokenType},
+    value::Value,
+};
+
+/// Folds subtrees made only of literals and operators into single literals,
+/// so `2 * 3 + x` becomes `6 + x`.
+///
+/// Variables and calls are never folded, since their meaning depends on the
+/// interpreter the tree is later evaluated with. Subtrees that fail to
+/// evaluate, such as `1 / 0`, are left alone so the error is still reported
+/// when the tree is run.
+///
+/// Comparisons are folded using `epsilon`, which should be the
+/// `Interpreter::epsilon` the tree will be evaluated with.
+pub fn optimize(expr: Expr, epsilon: f64) -> Expr {
+    let mut interpreter = Interpreter::empty();
+    interpreter.epsilon = epsilon;
+    let mut optimizer = Optimizer { interpreter };
+    optimizer.optimize(expr).0
+}
+
+struct Optimizer {
+    /// Used to evaluate constant subtrees. Only literals and operators are
+    /// ever evaluated, so the only state read is `epsilon`.
+    interpreter: Interpreter,
+}
+
+impl Optimizer {
+    /// Rewrites `expr`, returning the new node and whether it is free of
+    /// variables and calls.
+    fn optimize(&mut self, expr: Expr) -> (Expr, bool) {
+        match expr {
+            Expr::Binary(expr) => {
+                let (left, left_constant) = self.optimize(*expr.left);
+                let (right, right_constant) = self.optimize(*expr.right);
+                let folded = Expr::Binary(Binary {
+                    left: Box::new(left),
+                    operator: expr.operator.clone(),
+                    right: Box::new(right),
+                });
+                if left_constant && right_constant {
+                    self.fold(folded, &expr.operator)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Logical(expr) => {
+                let (left, left_constant) = self.optimize(*expr.left);
+                let (right, right_constant) = self.optimize(*expr.right);
+                let folded = Expr::Logical(Logical {
+                    left: Box::new(left),
+                    operator: expr.operator.clone(),
+                    right: Box::new(right),
+                });
+                if left_constant && right_constant {
+                    self.fold(folded, &expr.operator)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Conditional(expr) => {
+                let (condition, condition_constant) = self.optimize(*expr.condition);
+                let (then, then_constant) = self.optimize(*expr.then);
+                let (otherwise, otherwise_constant) = self.optimize(*expr.otherwise);
+                let folded = Expr::Conditional(Conditional {
+                    condition: Box::new(condition),
+                    question: expr.question.clone(),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                });
+                if condition_constant && then_constant && otherwise_constant {
+                    self.fold(folded, &expr.question)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Comparison(expr) => {
+                let mut constant = true;
+                let operands = expr
+                    .operands
+                    .into_iter()
+                    .map(|operand| {
+                        let (operand, operand_constant) = self.optimize(operand);
+                        constant &= operand_constant;
+                        operand
+                    })
+                    .collect();
+                let operator = expr.operators[0].clone();
+                let folded = Expr::Comparison(Comparison {
+                    operands,
+                    operators: expr.operators,
+                });
+                if constant {
+                    self.fold(folded, &operator)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Grouping(expr) => match self.optimize(*expr.expression) {
+                // The grouping has already done its job in the parse.
+                (expression, true) => (expression, true),
+                (expression, false) => (
+                    Expr::Grouping(Grouping {
+                        expression: Box::new(expression),
+                    }),
+                    false,
+                ),
+            },
+            Expr::List(expr) => {
+                let mut constant = true;
+                let elements = expr
+                    .elements
+                    .into_iter()
+                    .map(|element| {
+                        let (element, element_constant) = self.optimize(element);
+                        constant &= element_constant;
+                        element
+                    })
+                    .collect();
+                let list = Expr::List(List {
+                    bracket: expr.bracket,
+                    elements,
+                });
+                (list, constant)
+            }
+            Expr::Index(expr) => {
+                let (object, object_constant) = self.optimize(*expr.object);
+                let (index, index_constant) = self.optimize(*expr.index);
+                let folded = Expr::Index(Index {
+                    object: Box::new(object),
+                    bracket: expr.bracket.clone(),
+                    index: Box::new(index),
+                });
+                if object_constant && index_constant {
+                    self.fold(folded, &expr.bracket)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Literal(expr) => (Expr::Literal(expr), true),
+            Expr::Unary(expr) => {
+                let (right, constant) = self.optimize(*expr.right);
+                let folded = Expr::Unary(Unary {
+                    operator: expr.operator.clone(),
+                    right: Box::new(right),
+                });
+                if constant {
+                    self.fold(folded, &expr.operator)
+                } else {
+                    (folded, false)
+                }
+            }
+            Expr::Call(expr) => {
+                let arguments = expr
+                    .arguments
+                    .into_iter()
+                    .map(|argument| self.optimize(argument).0)
+                    .collect();
+                let call = Expr::Call(Call {
+                    callee: expr.callee,
+                    paren: expr.paren,
+                    arguments,
+                });
+                (call, false)
+            }
+            Expr::Variable(expr) => (Expr::Variable(expr), false),
+            Expr::Assignment(expr) => {
+                let assignment = Expr::Assignment(Assignment {
+                    name: expr.name,
+                    value: Box::new(self.optimize(*expr.value).0),
+                });
+                (assignment, false)
+            }
+            // The body is shared behind an `Arc`, so it is left as written.
+            Expr::FunctionDefinition(expr) => (Expr::FunctionDefinition(expr), false),
+        }
+    }
+
+    /// Evaluates a constant `expr`, replacing it with a literal at `token`'s
+    /// position if the result is a number.
+    fn fold(&mut self, expr: Expr, token: &Token) -> (Expr, bool) {
+        match self.interpreter.interpret(&expr) {
+            Ok(Value::Number(number)) => {
+                let literal = Expr::Literal(Literal {
+                    value: Token {
+                        kind: TokenType::Number,
+                        lexeme: number.to_string(),
+                        literal: Some(number),
+                        line: token.line,
+                        column: token.column,
+                    },
+                });
+                (literal, true)
+            }
+            Ok(_) => (expr, true),
+            Err(_) => (expr, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser::Parser, scanner::Scanner};
+
+    use super::*;
+
+    fn optimized(source: &str, epsilon: f64) -> String {
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        let expr = Parser::new(tokens).parse().unwrap();
+        optimize(expr, epsilon).display()
+    }
+
+    #[test]
+    fn constant_subtrees_are_folded_around_variables() {
+        assert_eq!(optimized("2 * 3 + x", 0.0), optimized("6 + x", 0.0));
+        assert_eq!(optimized("1 / 0", 0.0), optimized("1 / 0", 0.0));
+    }
+
+    #[test]
+    fn comparisons_are_folded_with_the_given_epsilon() {
+        // Booleans have no literal, so the comparison is only folded away
+        // as the condition of a conditional.
+        assert_eq!(optimized("1 == 1.000001 ? 2 : 3", 0.0), "3");
+        assert_eq!(optimized("1 == 1.000001 ? 2 : 3", 1e-3), "2");
+    }
+}
@@ -1,221 +1,951 @@
 use crate::{
     errors::{CalculatorError, CalculatorErrorType},
-    scanner::{Token, TokenType},
+    scanner::{self, Token, TokenType},
 };
 use core::fmt::Debug;
+use expressions::Expr;
+
+/// How deeply expressions may nest by default before parsing is aborted, so
+/// input like `((((...))))` can't overflow the stack. Each level of
+/// parentheses passes through every precedence rule, which costs around 45
+/// KiB of stack in a debug build, so this keeps within a thread's 8 MiB.
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// How tall a chain of operators such as `1 + 2 + 3` may make the tree, each
+/// operator adding a level. Parsing a chain is a loop, but evaluating,
+/// printing and optimizing the tree recurse once per level, so a long enough
+/// chain would overflow the stack there instead.
+const MAX_CHAIN_HEIGHT: usize = 256;
 
 #[derive(Clone)]
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    depth: usize,
+    max_depth: usize,
+    /// How many `|...|` absolute values are open, since a `|` after a `%`
+    /// could close one of them.
+    abs_depth: usize,
 }
 
 pub mod expressions {
-    use std::fmt::Formatter;
+    use std::{fmt::Formatter, sync::Arc};
 
     use super::*;
-    pub trait Expression: Debug {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError>;
-    }
 
-    pub trait Visitor {
-        fn visit_binary_expr(&mut self, expr: &Binary) -> Result<f64, CalculatorError>;
-        fn visit_grouping_expr(&mut self, expr: &Grouping) -> Result<f64, CalculatorError>;
-        fn visit_literal_expr(&mut self, expr: &Literal) -> Result<f64, CalculatorError>;
-        fn visit_unary_expr(&mut self, expr: &Unary) -> Result<f64, CalculatorError>;
-        fn visit_call_expr(&mut self, expr: &Call) -> Result<f64, CalculatorError>;
-        fn visit_variable_expr(&mut self, expr: &Variable) -> Result<f64, CalculatorError>;
+    /// A node of the syntax tree. Each variant wraps a struct holding its
+    /// fields, so code handling one kind of node can take it directly.
+    #[derive(Clone, PartialEq)]
+    pub enum Expr {
+        Binary(Binary),
+        Logical(Logical),
+        Conditional(Conditional),
+        Comparison(Comparison),
+        Grouping(Grouping),
+        List(List),
+        Index(Index),
+        Literal(Literal),
+        Unary(Unary),
+        Call(Call),
+        Variable(Variable),
+        Assignment(Assignment),
+        FunctionDefinition(FunctionDefinition),
     }
 
+    #[derive(Clone, PartialEq)]
     pub struct Binary {
-        pub left: Box<dyn Expression>,
+        pub left: Box<Expr>,
         pub operator: Token,
-        pub right: Box<dyn Expression>,
+        pub right: Box<Expr>,
     }
-    impl Expression for Binary {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_binary_expr(self)
-        }
+
+    /// An `and` or `or`, which only evaluates `right` when `left` doesn't
+    /// already decide the result.
+    #[derive(Clone, PartialEq)]
+    pub struct Logical {
+        pub left: Box<Expr>,
+        pub operator: Token,
+        pub right: Box<Expr>,
     }
-    impl Debug for Binary {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(
-                f,
-                "({} {:?} {:?})",
-                self.operator.lexeme, self.left, self.right
-            )
-        }
+
+    /// `condition ? then : otherwise`, which evaluates only the branch the
+    /// condition selects.
+    #[derive(Clone, PartialEq)]
+    pub struct Conditional {
+        pub condition: Box<Expr>,
+        pub question: Token,
+        pub then: Box<Expr>,
+        pub otherwise: Box<Expr>,
     }
 
+    /// A chain of two or more comparisons such as `1 < x <= 10`, which holds
+    /// when every adjacent pair does. Each operand is evaluated at most once,
+    /// from left to right, stopping at the first comparison that fails.
+    #[derive(Clone, PartialEq)]
+    pub struct Comparison {
+        /// One more operand than there are operators.
+        pub operands: Vec<Expr>,
+        pub operators: Vec<Token>,
+    }
+
+    #[derive(Clone, PartialEq)]
     pub struct Grouping {
-        pub expression: Box<dyn Expression>,
+        pub expression: Box<Expr>,
     }
-    impl Expression for Grouping {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_grouping_expr(self)
-        }
+
+    /// A vector literal such as `[1, 2, 3]`.
+    #[derive(Clone, PartialEq)]
+    pub struct List {
+        /// The opening bracket, where errors in the elements are reported.
+        pub bracket: Token,
+        pub elements: Vec<Expr>,
     }
-    impl Debug for Grouping {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "({:?})", self.expression)
-        }
+
+    /// An element of a vector, such as `v[0]`. Indices start at 0.
+    #[derive(Clone, PartialEq)]
+    pub struct Index {
+        pub object: Box<Expr>,
+        /// The opening bracket, where a bad index is reported.
+        pub bracket: Token,
+        pub index: Box<Expr>,
     }
 
+    #[derive(Clone, PartialEq)]
     pub struct Literal {
         pub value: Token,
     }
-    impl Expression for Literal {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_literal_expr(self)
-        }
-    }
-    impl Debug for Literal {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.value.lexeme)
-        }
-    }
 
+    #[derive(Clone, PartialEq)]
     pub struct Unary {
         pub operator: Token,
-        pub right: Box<dyn Expression>,
-    }
-    impl Expression for Unary {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_unary_expr(self)
-        }
-    }
-    impl Debug for Unary {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "({} {:?})", self.operator.lexeme, self.right)
-        }
+        pub right: Box<Expr>,
     }
 
+    #[derive(Clone, PartialEq)]
     pub struct Call {
         pub callee: Token,
         pub paren: Token,
-        pub arguments: Vec<Box<dyn Expression>>,
+        pub arguments: Vec<Expr>,
     }
-    impl Expression for Call {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_call_expr(self)
-        }
+
+    #[derive(Clone, PartialEq)]
+    pub struct Variable {
+        pub name: Token,
     }
-    impl Debug for Call {
-        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "({} {:?})", self.callee.lexeme, self.arguments)
-        }
+
+    #[derive(Clone, PartialEq)]
+    pub struct Assignment {
+        pub name: Token,
+        pub value: Box<Expr>,
     }
 
-    pub struct Variable {
+    #[derive(Clone, PartialEq)]
+    pub struct FunctionDefinition {
         pub name: Token,
+        pub parameters: Vec<Token>,
+        /// Shared so the interpreter can keep the body after the rest of the
+        /// tree is dropped.
+        pub body: Arc<Expr>,
     }
-    impl Expression for Variable {
-        fn accept(&self, visitor: &mut dyn Visitor) -> Result<f64, CalculatorError> {
-            visitor.visit_variable_expr(self)
+
+    impl Expr {
+        /// The name if this expression is a bare variable, which special
+        /// forms like `sum(i, 1, 10, i^2)` use to find their bound variable.
+        pub fn as_variable(&self) -> Option<&Token> {
+            match self {
+                Expr::Variable(expr) => Some(&expr.name),
+                _ => None,
+            }
+        }
+
+        /// Compares two trees by shape and by the text of their tokens,
+        /// ignoring where in the source the tokens were. The derived
+        /// `PartialEq` also compares positions, so `2+3` and `2 + 3` are only
+        /// equal structurally.
+        pub fn structural_eq(&self, other: &Expr) -> bool {
+            fn token(a: &Token, b: &Token) -> bool {
+                a.kind == b.kind && a.lexeme == b.lexeme
+            }
+            fn tokens(a: &[Token], b: &[Token]) -> bool {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| token(a, b))
+            }
+            fn exprs(a: &[Expr], b: &[Expr]) -> bool {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.structural_eq(b))
+            }
+            match (self, other) {
+                (Expr::Binary(a), Expr::Binary(b)) => {
+                    token(&a.operator, &b.operator)
+                        && a.left.structural_eq(&b.left)
+                        && a.right.structural_eq(&b.right)
+                }
+                (Expr::Logical(a), Expr::Logical(b)) => {
+                    token(&a.operator, &b.operator)
+                        && a.left.structural_eq(&b.left)
+                        && a.right.structural_eq(&b.right)
+                }
+                (Expr::Conditional(a), Expr::Conditional(b)) => {
+                    a.condition.structural_eq(&b.condition)
+                        && a.then.structural_eq(&b.then)
+                        && a.otherwise.structural_eq(&b.otherwise)
+                }
+                (Expr::Comparison(a), Expr::Comparison(b)) => {
+                    tokens(&a.operators, &b.operators) && exprs(&a.operands, &b.operands)
+                }
+                (Expr::Grouping(a), Expr::Grouping(b)) => a.expression.structural_eq(&b.expression),
+                (Expr::List(a), Expr::List(b)) => exprs(&a.elements, &b.elements),
+                (Expr::Index(a), Expr::Index(b)) => {
+                    a.object.structural_eq(&b.object) && a.index.structural_eq(&b.index)
+                }
+                (Expr::Literal(a), Expr::Literal(b)) => token(&a.value, &b.value),
+                (Expr::Unary(a), Expr::Unary(b)) => {
+                    token(&a.operator, &b.operator) && a.right.structural_eq(&b.right)
+                }
+                (Expr::Call(a), Expr::Call(b)) => {
+                    token(&a.callee, &b.callee) && exprs(&a.arguments, &b.arguments)
+                }
+                (Expr::Variable(a), Expr::Variable(b)) => token(&a.name, &b.name),
+                (Expr::Assignment(a), Expr::Assignment(b)) => {
+                    token(&a.name, &b.name) && a.value.structural_eq(&b.value)
+                }
+                (Expr::FunctionDefinition(a), Expr::FunctionDefinition(b)) => {
+                    token(&a.name, &b.name)
+                        && tokens(&a.parameters, &b.parameters)
+                        && a.body.structural_eq(&b.body)
+                }
+                _ => false,
+            }
+        }
+
+        /// The number of nodes in the tree, including this one.
+        pub fn node_count(&self) -> usize {
+            1 + match self {
+                Expr::Binary(expr) => expr.left.node_count() + expr.right.node_count(),
+                Expr::Logical(expr) => expr.left.node_count() + expr.right.node_count(),
+                Expr::Conditional(expr) => {
+                    expr.condition.node_count()
+                        + expr.then.node_count()
+                        + expr.otherwise.node_count()
+                }
+                Expr::Comparison(expr) => expr.operands.iter().map(Expr::node_count).sum(),
+                Expr::Grouping(expr) => expr.expression.node_count(),
+                Expr::List(expr) => expr.elements.iter().map(Expr::node_count).sum(),
+                Expr::Index(expr) => expr.object.node_count() + expr.index.node_count(),
+                Expr::Literal(_) | Expr::Variable(_) => 0,
+                Expr::Unary(expr) => expr.right.node_count(),
+                Expr::Call(expr) => expr.arguments.iter().map(Expr::node_count).sum(),
+                Expr::Assignment(expr) => expr.value.node_count(),
+                Expr::FunctionDefinition(expr) => expr.body.node_count(),
+            }
+        }
+
+        /// The number of nodes on the longest path down from this one to a
+        /// leaf, so a lone literal has a height of 1.
+        pub fn height(&self) -> usize {
+            1 + match self {
+                Expr::Binary(expr) => expr.left.height().max(expr.right.height()),
+                Expr::Logical(expr) => expr.left.height().max(expr.right.height()),
+                Expr::Conditional(expr) => expr
+                    .condition
+                    .height()
+                    .max(expr.then.height())
+                    .max(expr.otherwise.height()),
+                Expr::Comparison(expr) => expr.operands.iter().map(Expr::height).max().unwrap_or(0),
+                Expr::Grouping(expr) => expr.expression.height(),
+                Expr::List(expr) => expr.elements.iter().map(Expr::height).max().unwrap_or(0),
+                Expr::Index(expr) => expr.object.height().max(expr.index.height()),
+                Expr::Literal(_) | Expr::Variable(_) => 0,
+                Expr::Unary(expr) => expr.right.height(),
+                Expr::Call(expr) => expr.arguments.iter().map(Expr::height).max().unwrap_or(0),
+                Expr::Assignment(expr) => expr.value.height(),
+                Expr::FunctionDefinition(expr) => expr.body.height(),
+            }
+        }
+
+        /// Renders the expression as fully parenthesized infix notation, such
+        /// as `((2 + 3) * 4)`, which can usually be parsed back.
+        pub fn display(&self) -> String {
+            match self {
+                Expr::Binary(expr) => format!(
+                    "({} {} {})",
+                    expr.left.display(),
+                    expr.operator.lexeme,
+                    expr.right.display()
+                ),
+                Expr::Logical(expr) => format!(
+                    "({} {} {})",
+                    expr.left.display(),
+                    expr.operator.lexeme,
+                    expr.right.display()
+                ),
+                Expr::Conditional(expr) => format!(
+                    "({} ? {} : {})",
+                    expr.condition.display(),
+                    expr.then.display(),
+                    expr.otherwise.display()
+                ),
+                Expr::Comparison(expr) => {
+                    let mut chain = expr.operands[0].display();
+                    for (operator, operand) in expr.operators.iter().zip(&expr.operands[1..]) {
+                        chain.push_str(&format!(" {} {}", operator.lexeme, operand.display()));
+                    }
+                    format!("({})", chain)
+                }
+                // Binary and unary expressions already add their own parentheses.
+                Expr::Grouping(expr) => expr.expression.display(),
+                Expr::List(expr) => {
+                    let elements: Vec<_> = expr.elements.iter().map(Expr::display).collect();
+                    format!("[{}]", elements.join(", "))
+                }
+                Expr::Index(expr) => format!("{}[{}]", expr.object.display(), expr.index.display()),
+                Expr::Literal(expr) => expr.value.lexeme.clone(),
+                Expr::Unary(expr) => match expr.operator.kind {
+                    TokenType::Bang | TokenType::Modulo => {
+                        format!("({}{})", expr.right.display(), expr.operator.lexeme)
+                    }
+                    TokenType::Bar => format!("|{}|", expr.right.display()),
+                    TokenType::Not => format!("(not {})", expr.right.display()),
+                    _ => format!("({}{})", expr.operator.lexeme, expr.right.display()),
+                },
+                Expr::Call(expr) => {
+                    let arguments: Vec<_> = expr.arguments.iter().map(Expr::display).collect();
+                    format!("{}({})", expr.callee.lexeme, arguments.join(", "))
+                }
+                Expr::Variable(expr) => expr.name.lexeme.clone(),
+                Expr::Assignment(expr) => {
+                    format!("{} = {}", expr.name.lexeme, expr.value.display())
+                }
+                Expr::FunctionDefinition(expr) => {
+                    let parameters: Vec<_> =
+                        expr.parameters.iter().map(|p| p.lexeme.as_str()).collect();
+                    format!(
+                        "{}({}) = {}",
+                        expr.name.lexeme,
+                        parameters.join(", "),
+                        expr.body.display()
+                    )
+                }
+            }
         }
     }
-    impl Debug for Variable {
+
+    /// A compact Lisp-like form, such as `(+ 1 (* 2 "x"))`, used by `--debug`.
+    impl Debug for Expr {
         fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{:?}", self.name.lexeme)
+            match self {
+                Expr::Binary(expr) => write!(
+                    f,
+                    "({} {:?} {:?})",
+                    expr.operator.lexeme, expr.left, expr.right
+                ),
+                Expr::Logical(expr) => write!(
+                    f,
+                    "({} {:?} {:?})",
+                    expr.operator.lexeme, expr.left, expr.right
+                ),
+                Expr::Conditional(expr) => write!(
+                    f,
+                    "(? {:?} {:?} {:?})",
+                    expr.condition, expr.then, expr.otherwise
+                ),
+                Expr::Comparison(expr) => {
+                    write!(f, "(chain {:?}", expr.operands[0])?;
+                    for (operator, operand) in expr.operators.iter().zip(&expr.operands[1..]) {
+                        write!(f, " {} {:?}", operator.lexeme, operand)?;
+                    }
+                    write!(f, ")")
+                }
+                Expr::Grouping(expr) => write!(f, "({:?})", expr.expression),
+                Expr::List(expr) => write!(f, "(list {:?})", expr.elements),
+                Expr::Index(expr) => write!(f, "(index {:?} {:?})", expr.object, expr.index),
+                Expr::Literal(expr) => write!(f, "{}", expr.value.lexeme),
+                Expr::Unary(expr) => write!(f, "({} {:?})", expr.operator.lexeme, expr.right),
+                Expr::Call(expr) => write!(f, "({} {:?})", expr.callee.lexeme, expr.arguments),
+                Expr::Variable(expr) => write!(f, "{:?}", expr.name.lexeme),
+                Expr::Assignment(expr) => {
+                    write!(f, "(= {:?} {:?})", expr.name.lexeme, expr.value)
+                }
+                Expr::FunctionDefinition(expr) => {
+                    let parameters: Vec<_> = expr.parameters.iter().map(|p| &p.lexeme).collect();
+                    write!(
+                        f,
+                        "(fn {:?} {:?} {:?})",
+                        expr.name.lexeme, parameters, expr.body
+                    )
+                }
+            }
         }
     }
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser {
+            tokens,
+            current: 0,
+            depth: 0,
+            max_depth: MAX_NESTING_DEPTH,
+            abs_depth: 0,
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
-        let expr = self.expression()?;
-        if !self.is_at_end() {
-            return Err(CalculatorError {
-                error: CalculatorErrorType::AdditionalCodeAfterEnd,
-                token: Some(self.peek()),
+    /// Sets how deeply parentheses, unary operators, exponents and
+    /// assignments may nest before parsing fails with `NestingTooDeep`.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Parses the tokens into a single expression. On a syntax error the
+    /// parser synchronizes and keeps going, so every error in the input is
+    /// reported at once.
+    pub fn parse(&mut self) -> Result<Expr, Vec<CalculatorError>> {
+        // Without separators, a successful parse is exactly one expression.
+        self.expressions(false)
+            .map(|mut expressions| expressions.remove(0))
+    }
+
+    /// Parses a program of expressions separated by semicolons, such as
+    /// `x = 3; y = 4; x^2 + y^2`, allowing one trailing semicolon. Like
+    /// `parse`, every syntax error is reported at once.
+    pub fn program(&mut self) -> Result<Vec<Expr>, Vec<CalculatorError>> {
+        self.expressions(true)
+    }
+
+    /// Parses expressions up to the end of the input, separated by
+    /// semicolons if `semicolons` is set. After an error the parser
+    /// synchronizes and keeps going, collecting every error.
+    fn expressions(&mut self, semicolons: bool) -> Result<Vec<Expr>, Vec<CalculatorError>> {
+        let mut expressions = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            let error = match self.expression() {
+                Ok(expr) if semicolons && self.match_token(&[TokenType::Semicolon]) => {
+                    expressions.push(expr);
+                    if self.is_at_end() {
+                        break;
+                    }
+                    continue;
+                }
+                Ok(expr) if self.is_at_end() => {
+                    expressions.push(expr);
+                    break;
+                }
+                Ok(_) => self
+                    .clone()
+                    .create_error(CalculatorErrorType::AdditionalCodeAfterEnd),
+                Err(error) => error,
+            };
+            // Resuming after a nesting error would only report it again for
+            // every level of the remaining input.
+            let fatal = error.error == CalculatorErrorType::NestingTooDeep;
+            errors.push(error);
+            if fatal {
+                break;
+            }
+            self.synchronize();
+            if self.is_at_end() {
+                break;
+            }
+        }
+        if errors.is_empty() {
+            Ok(expressions)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Skips past the token that caused an error and any following tokens
+    /// that can't begin an expression, so parsing can resume without
+    /// reporting a cascade of errors for one mistake.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end()
+            && !self.check(&TokenType::Number)
+            && !self.check(&TokenType::Identifier)
+            && !self.check(&TokenType::LeftParen)
+            && !self.check(&TokenType::LeftBracket)
+            && !self.check(&TokenType::Minus)
+            && !self.check(&TokenType::Plus)
+            && !self.check(&TokenType::Not)
+            && !self.check(&TokenType::True)
+            && !self.check(&TokenType::False)
+        {
+            self.advance();
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, CalculatorError> {
+        self.assignment()
+    }
+
+    /// Assignment is right-associative and evaluates to the assigned value,
+    /// so `a = b = 3` sets both names.
+    fn assignment(&mut self) -> Result<Expr, CalculatorError> {
+        self.nested(Parser::assignment_inner)
+    }
+
+    fn assignment_inner(&mut self) -> Result<Expr, CalculatorError> {
+        if self.is_function_definition() {
+            return self.function_definition();
+        }
+
+        if scanner::is_keyword(&self.peek().lexeme) && self.check_next(&TokenType::Equal) {
+            return Err(self
+                .clone()
+                .create_error(CalculatorErrorType::ReservedName(self.peek().lexeme)));
+        }
+
+        if self.check(&TokenType::Identifier) && self.check_next(&TokenType::Equal) {
+            let name = self.advance();
+            self.advance();
+            let value = self.assignment()?;
+            return Ok(Expr::Assignment(expressions::Assignment {
+                name,
+                value: Box::new(value),
+            }));
+        }
+
+        self.conditional()
+    }
+
+    /// Looks ahead for `name(a, b, ...) =` without consuming anything, since
+    /// until the `=` it is indistinguishable from a call.
+    fn is_function_definition(&self) -> bool {
+        let kind = |index: usize| self.tokens.get(index).map(|token| &token.kind);
+        let mut index = self.current;
+        if kind(index) != Some(&TokenType::Identifier)
+            || kind(index + 1) != Some(&TokenType::LeftParen)
+        {
+            return false;
+        }
+        index += 2;
+        if kind(index) == Some(&TokenType::RightParen) {
+            return kind(index + 1) == Some(&TokenType::Equal);
+        }
+        loop {
+            if kind(index) != Some(&TokenType::Identifier) {
+                return false;
+            }
+            index += 1;
+            match kind(index) {
+                Some(TokenType::Comma) => index += 1,
+                Some(TokenType::RightParen) => return kind(index + 1) == Some(&TokenType::Equal),
+                _ => return false,
+            }
+        }
+    }
+
+    fn function_definition(&mut self) -> Result<Expr, CalculatorError> {
+        let name = self.advance();
+        self.consume(TokenType::LeftParen, "Expected '(' after function name.")?;
+        let mut parameters = Vec::new();
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                // Only one of the arguments could ever be seen, so repeating
+                // a name is certainly a mistake.
+                if parameters
+                    .iter()
+                    .any(|parameter: &Token| parameter.lexeme == self.peek().lexeme)
+                {
+                    return Err(self.clone().create_error(CalculatorErrorType::SyntaxError(
+                        format!("Duplicate parameter name '{}'.", self.peek().lexeme),
+                    )));
+                }
+                parameters.push(self.consume(TokenType::Identifier, "Expected parameter name.")?);
+                if !self.match_token(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters.")?;
+        self.consume(TokenType::Equal, "Expected '=' after parameters.")?;
+        let body = self.expression()?;
+        Ok(Expr::FunctionDefinition(expressions::FunctionDefinition {
+            name,
+            parameters,
+            body: body.into(),
+        }))
+    }
+
+    /// The conditional binds loosest of all operators and is
+    /// right-associative, so `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<Expr, CalculatorError> {
+        let condition = self.or()?;
+
+        if self.match_token(&[TokenType::Question]) {
+            let question = self.previous();
+            let then = self.expression()?;
+            self.consume(TokenType::Colon, "Expected ':' after the '?' branch.")?;
+            let otherwise = self.nested(Parser::conditional)?;
+            return Ok(Expr::Conditional(expressions::Conditional {
+                condition: Box::new(condition),
+                question,
+                then: Box::new(then),
+                otherwise: Box::new(otherwise),
+            }));
+        }
+
+        Ok(condition)
+    }
+
+    /// `or` binds looser than `and`, which binds looser than `not`, so
+    /// `not a or b and c` is `(not a) or (b and c)`.
+    fn or(&mut self) -> Result<Expr, CalculatorError> {
+        let mut expr = self.and()?;
+        let mut height = None;
+
+        while self.match_token(&[TokenType::Or]) {
+            let operator = self.previous();
+            let right = self.and()?;
+            self.extend_chain(&mut height, &expr, &right)?;
+            expr = Expr::Logical(expressions::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
             });
         }
+
         Ok(expr)
     }
 
-    fn expression(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
-        self.addition()
+    fn and(&mut self) -> Result<Expr, CalculatorError> {
+        let mut expr = self.not()?;
+        let mut height = None;
+
+        while self.match_token(&[TokenType::And]) {
+            let operator = self.previous();
+            let right = self.not()?;
+            self.extend_chain(&mut height, &expr, &right)?;
+            expr = Expr::Logical(expressions::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
+        }
+
+        Ok(expr)
     }
 
-    fn addition(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
+    /// `not` binds looser than comparisons, so `not x == 0` negates the
+    /// whole comparison.
+    fn not(&mut self) -> Result<Expr, CalculatorError> {
+        if self.match_token(&[TokenType::Not]) {
+            let operator = self.previous();
+            let right = self.nested(Parser::not)?;
+            return Ok(Expr::Unary(expressions::Unary {
+                operator,
+                right: Box::new(right),
+            }));
+        }
+
+        self.comparison()
+    }
+
+    /// Comparisons chain like they do in mathematics, so `1 < x < 10` means
+    /// `1 < x and x < 10` rather than comparing the boolean `1 < x` with 10.
+    /// A single comparison stays a plain binary expression.
+    fn comparison(&mut self) -> Result<Expr, CalculatorError> {
+        let mut operands = vec![self.addition()?];
+        let mut operators = Vec::new();
+
+        while self.match_token(&[
+            TokenType::Less,
+            TokenType::Greater,
+            TokenType::LessEqual,
+            TokenType::GreaterEqual,
+            TokenType::EqualEqual,
+            TokenType::BangEqual,
+        ]) {
+            operators.push(self.previous());
+            operands.push(self.addition()?);
+        }
+
+        Ok(match operators.len() {
+            0 => operands.remove(0),
+            1 => {
+                let right = operands.pop().unwrap();
+                Expr::Binary(expressions::Binary {
+                    left: Box::new(operands.pop().unwrap()),
+                    operator: operators.remove(0),
+                    right: Box::new(right),
+                })
+            }
+            _ => Expr::Comparison(expressions::Comparison {
+                operands,
+                operators,
+            }),
+        })
+    }
+
+    fn addition(&mut self) -> Result<Expr, CalculatorError> {
         let mut expr = self.multiplication()?;
+        let mut height = None;
 
         while self.match_token(&[TokenType::Plus, TokenType::Minus]) {
             let operator = self.previous();
             let right = self.multiplication()?;
-            expr = Box::new(expressions::Binary {
-                left: expr,
+            self.extend_chain(&mut height, &expr, &right)?;
+            expr = Expr::Binary(expressions::Binary {
+                left: Box::new(expr),
                 operator,
-                right,
+                right: Box::new(right),
             });
         }
 
         Ok(expr)
     }
 
-    fn multiplication(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
-        let mut expr = self.power()?;
+    fn multiplication(&mut self) -> Result<Expr, CalculatorError> {
+        let mut expr = self.implicit_multiplication()?;
+        let mut height = None;
 
-        while self.match_token(&[TokenType::Star, TokenType::Slash]) {
+        while self.match_token(&[TokenType::Star, TokenType::Slash, TokenType::Modulo]) {
             let operator = self.previous();
-            let right = self.power()?;
-            expr = Box::new(expressions::Binary {
-                left: expr,
+            let right = self.implicit_multiplication()?;
+            self.extend_chain(&mut height, &expr, &right)?;
+            expr = Expr::Binary(expressions::Binary {
+                left: Box::new(expr),
                 operator,
-                right,
+                right: Box::new(right),
             });
         }
 
         Ok(expr)
     }
 
-    fn power(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
+    /// Adjacent factors such as `2pi` or `(1 + 2)(3 + 4)` are multiplied.
+    /// This binds tighter than explicit `*` and `/`, so `1 / 2pi` is
+    /// `1 / (2 * pi)`.
+    fn implicit_multiplication(&mut self) -> Result<Expr, CalculatorError> {
         let mut expr = self.unary()?;
+        let mut height = None;
 
-        while self.match_token(&[TokenType::Caret]) {
-            let operator = self.previous();
+        while self.check(&TokenType::Number)
+            || self.check(&TokenType::LeftParen)
+            || self.check(&TokenType::Identifier)
+            || self.check(&TokenType::Root)
+        {
+            let next = self.peek();
+            let operator = Token {
+                kind: TokenType::Star,
+                lexeme: "*".to_string(),
+                literal: None,
+                line: next.line,
+                column: next.column,
+            };
             let right = self.unary()?;
-            expr = Box::new(expressions::Binary {
-                left: expr,
+            self.extend_chain(&mut height, &expr, &right)?;
+            expr = Expr::Binary(expressions::Binary {
+                left: Box::new(expr),
                 operator,
-                right,
+                right: Box::new(right),
             });
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
+    /// Unary operators bind tighter than multiplication but looser than
+    /// exponentiation, so `-2 ^ 2` is `-(2 ^ 2)`.
+    fn unary(&mut self) -> Result<Expr, CalculatorError> {
         if self.match_token(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
-            let right = self.expression()?;
-            return Ok(Box::new(expressions::Unary { operator, right }));
+            let right = self.nested(Parser::unary)?;
+            return Ok(Expr::Unary(expressions::Unary {
+                operator,
+                right: Box::new(right),
+            }));
+        }
+
+        // `√` is shorthand for a call to `sqrt`, binding like the other
+        // prefix operators, so `√x^2` is `sqrt(x^2)`.
+        if self.match_token(&[TokenType::Root]) {
+            let root = self.previous();
+            let argument = self.nested(Parser::unary)?;
+            return Ok(Expr::Call(expressions::Call {
+                callee: Token {
+                    kind: TokenType::Identifier,
+                    lexeme: "sqrt".to_string(),
+                    ..root.clone()
+                },
+                paren: root,
+                arguments: vec![argument],
+            }));
+        }
+
+        self.power()
+    }
+
+    /// Exponentiation is right-associative, so `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+    fn power(&mut self) -> Result<Expr, CalculatorError> {
+        let expr = self.postfix()?;
+
+        if self.match_token(&[TokenType::Caret]) {
+            let operator = self.previous();
+            let right = self.nested(Parser::unary)?;
+            return Ok(Expr::Binary(expressions::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }));
+        }
+
+        Ok(expr)
+    }
+
+    /// Only a single factorial is allowed per operand; `3!!` is rejected
+    /// rather than guessing between `(3!)!` and a double factorial.
+    ///
+    /// A `%` is a postfix percent, dividing by 100, unless it is directly
+    /// followed by something that starts an operand, such as a number,
+    /// identifier, `(`, `[` or `√`, in which case it is left for
+    /// `multiplication` to read as modulo. So `10 % 3` and `10 % √4` are
+    /// modulo while `50%` and `200 * 15%` are percentages. A `|` after a `%`
+    /// opens an absolute value, as in `10 % |x|`, unless one is already open,
+    /// so `|50%|` is still a percentage. A `%` followed by a sign is
+    /// ambiguous, since `10 % -3` and `50% - 3` look alike, so it is a syntax
+    /// error; write `10 % (-3)` for the modulo or `(50%) - 3` for the
+    /// percentage.
+    fn postfix(&mut self) -> Result<Expr, CalculatorError> {
+        let mut expr = self.primary()?;
+        let mut height = None;
+
+        // Indexing binds tightest of all and can repeat, as in `f(x)[0]`.
+        while self.match_token(&[TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let index = self.expression()?;
+            self.consume(TokenType::RightBracket, "Expected ']' after index.")?;
+            self.extend_chain(&mut height, &expr, &index)?;
+            expr = Expr::Index(expressions::Index {
+                object: Box::new(expr),
+                bracket,
+                index: Box::new(index),
+            });
+        }
+
+        // A superscript is an exponent that binds tighter than any operator,
+        // so `-3²` is `-(3^2)` and `x²!` is `(x^2)!`.
+        if self.match_token(&[TokenType::Superscript]) {
+            let superscript = self.previous();
+            let exponent = superscript.literal.unwrap_or(0.0);
+            expr = Expr::Binary(expressions::Binary {
+                left: Box::new(expr),
+                operator: Token {
+                    kind: TokenType::Caret,
+                    lexeme: "^".to_string(),
+                    ..superscript.clone()
+                },
+                right: Box::new(Expr::Literal(expressions::Literal {
+                    value: Token {
+                        kind: TokenType::Number,
+                        lexeme: exponent.to_string(),
+                        ..superscript
+                    },
+                })),
+            });
+        }
+
+        if self.match_token(&[TokenType::Bang]) {
+            let operator = self.previous();
+            expr = Expr::Unary(expressions::Unary {
+                operator,
+                right: Box::new(expr),
+            });
         }
 
-        self.primary()
+        if self.check(&TokenType::Modulo)
+            && (self.check_next(&TokenType::Minus) || self.check_next(&TokenType::Plus))
+        {
+            return Err(self.clone().create_error(CalculatorErrorType::SyntaxError(
+                "Ambiguous '%' before a sign; write 'a % (-b)' for modulo or '(a%) - b' for a percentage."
+                    .to_string(),
+            )));
+        }
+
+        if self.check(&TokenType::Modulo) && !self.modulo_follows() {
+            let operator = self.advance();
+            expr = Expr::Unary(expressions::Unary {
+                operator,
+                right: Box::new(expr),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    /// Whether the `%` at the current token is followed by an operand, making
+    /// it a modulo rather than a percent.
+    fn modulo_follows(&self) -> bool {
+        let starts_operand = [
+            TokenType::Number,
+            TokenType::Identifier,
+            TokenType::LeftParen,
+            TokenType::LeftBracket,
+            TokenType::Root,
+            TokenType::True,
+            TokenType::False,
+            TokenType::Not,
+        ]
+        .iter()
+        .any(|kind| self.check_next(kind));
+        starts_operand || (self.abs_depth == 0 && self.check_next(&TokenType::Bar))
     }
 
-    fn primary(&mut self) -> Result<Box<dyn expressions::Expression>, CalculatorError> {
-        let mut expr: Box<dyn expressions::Expression>;
-        if self.match_token(&[TokenType::Number]) {
-            expr = Box::new(expressions::Literal {
+    fn primary(&mut self) -> Result<Expr, CalculatorError> {
+        let mut expr: Expr;
+        if self.match_token(&[TokenType::Number, TokenType::True, TokenType::False]) {
+            expr = Expr::Literal(expressions::Literal {
                 value: self.previous(),
             });
         } else if self.match_token(&[TokenType::LeftParen]) {
             expr = self.expression()?;
             self.consume(TokenType::RightParen, "Expected ')' after expression.")?;
-            expr = Box::new(expressions::Grouping { expression: expr });
+            expr = Expr::Grouping(expressions::Grouping {
+                expression: Box::new(expr),
+            });
+        } else if self.match_token(&[TokenType::LeftBracket]) {
+            let bracket = self.previous();
+            let mut elements = Vec::new();
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    // A trailing comma is allowed, as in calls.
+                    if !self.match_token(&[TokenType::Comma])
+                        || self.check(&TokenType::RightBracket)
+                    {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RightBracket, "Expected ']' after elements.")?;
+            expr = Expr::List(expressions::List { bracket, elements });
+        } else if self.match_token(&[TokenType::Bar]) {
+            // A bar is only ever an opener in this position, and the inner
+            // expression stops at the next bar it can't use, so `|a| + |b|`
+            // and `||x| - 1|` both parse. As a consequence `2|x|` isn't read
+            // as an implicit multiplication.
+            let operator = self.previous();
+            self.abs_depth += 1;
+            let right = self.expression();
+            self.abs_depth -= 1;
+            let right = right?;
+            self.consume(TokenType::Bar, "Expected '|' after expression.")?;
+            expr = Expr::Unary(expressions::Unary {
+                operator,
+                right: Box::new(right),
+            });
         } else if self.match_token(&[TokenType::Identifier]) {
             let name = self.previous();
+            // Whitespace is dropped by the scanner, so `sin (x)` is still a
+            // call while a bare `sin` is a variable reference.
             if !self.match_token(&[TokenType::LeftParen]) {
-                return Ok(Box::new(expressions::Variable { name }));
+                return Ok(Expr::Variable(expressions::Variable { name }));
             }
             let mut arguments = Vec::new();
-            if !self.match_token(&[TokenType::RightParen]) {
+            // `paren` is always the closing parenthesis, with or without
+            // arguments.
+            let paren = if self.match_token(&[TokenType::RightParen]) {
+                self.previous()
+            } else {
                 loop {
                     if arguments.len() >= 255 {
                         return Err(self
@@ -223,15 +953,17 @@ impl Parser {
                             .create_error(CalculatorErrorType::TooManyArguments));
                     }
                     arguments.push(self.expression()?);
-                    if !self.match_token(&[TokenType::Comma]) {
+                    // A single trailing comma is allowed, as in `f(1, 2,)`.
+                    if !self.match_token(&[TokenType::Comma]) || self.check(&TokenType::RightParen)
+                    {
                         break;
                     }
                 }
-                self.consume(TokenType::RightParen, "Expected ')' after arguments.")?;
-            }
-            expr = Box::new(expressions::Call {
+                self.consume(TokenType::RightParen, "Expected ')' after arguments.")?
+            };
+            expr = Expr::Call(expressions::Call {
                 callee: name,
-                paren: self.previous(),
+                paren,
                 arguments,
             });
         } else {
@@ -240,19 +972,60 @@ impl Parser {
                 .create_error(CalculatorErrorType::ExpectedExpression));
         }
 
-        // if self.match_token(&[TokenType::LeftParen]) {
-        //     let to_multiply = self.expression()?;
-        //     self.consume(TokenType::RightParen, "Expected ')' after expression.")?;
-        //     expr = Box::new(expressions::ParanthesisMultiplication {
-        //         left: expr,
-        //         right: to_multiply,
-        //     });
-        // }
-
         Ok(expr)
     }
 
+    /// Runs `parse` one level deeper, failing instead if that would exceed
+    /// `max_depth`. Every recursive rule passes through here.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Parser) -> Result<Expr, CalculatorError>,
+    ) -> Result<Expr, CalculatorError> {
+        if self.depth >= self.max_depth {
+            return Err(self
+                .clone()
+                .create_error(CalculatorErrorType::NestingTooDeep));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Records that `left` and `right` are about to be joined into the next
+    /// link of a chain, where `height` is the height of `left` once known.
+    /// Fails with `NestingTooDeep` if the chain would pass
+    /// `MAX_CHAIN_HEIGHT`.
+    fn extend_chain(
+        &self,
+        height: &mut Option<usize>,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<(), CalculatorError> {
+        let left = height.unwrap_or_else(|| left.height());
+        let joined = left.max(right.height()) + 1;
+        if joined > MAX_CHAIN_HEIGHT {
+            return Err(self
+                .clone()
+                .create_error(CalculatorErrorType::NestingTooDeep));
+        }
+        *height = Some(joined);
+        Ok(())
+    }
+
     fn create_error(self, error: CalculatorErrorType) -> CalculatorError {
+        // No rule uses `.`, so whatever the parser expected instead, the
+        // more useful thing to say is that the dot doesn't belong.
+        let error = match error {
+            CalculatorErrorType::ExpectedExpression
+            | CalculatorErrorType::AdditionalCodeAfterEnd
+            | CalculatorErrorType::SyntaxError(_)
+                if self.check(&TokenType::Dot) =>
+            {
+                CalculatorErrorType::UnexpectedToken(TokenType::Dot)
+            }
+            error => error,
+        };
         CalculatorError {
             error,
             token: Some(self.peek()),
@@ -285,6 +1058,13 @@ impl Parser {
         self.peek().kind == *kind
     }
 
+    fn check_next(&self, kind: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.kind == *kind,
+            None => false,
+        }
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -304,3 +1084,261 @@ impl Parser {
         self.peek().kind == TokenType::Eof
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::value::Value;
+
+    use super::*;
+
+    /// The fully parenthesized form of `source`, which shows how it parsed.
+    fn display(source: &str) -> String {
+        crate::parse(source).unwrap().display()
+    }
+
+    /// Whether `a` and `b` parse to the same tree, wherever their tokens are.
+    fn same(a: &str, b: &str) -> bool {
+        crate::parse(a)
+            .unwrap()
+            .structural_eq(&crate::parse(b).unwrap())
+    }
+
+    fn value(source: &str) -> f64 {
+        match crate::eval(source).unwrap() {
+            Value::Number(number) => number,
+            value => panic!("expected a number but got {}", value),
+        }
+    }
+
+    #[test]
+    fn unary_binds_tighter_than_addition_and_multiplication() {
+        assert_eq!(display("-2 + 3"), "((-2) + 3)");
+        assert_eq!(value("-2 + 3"), 1.0);
+        assert_eq!(display("-2 * 3"), "((-2) * 3)");
+        assert_eq!(value("-2 * 3"), -6.0);
+        assert_eq!(display("--5"), "(-(-5))");
+        assert_eq!(value("--5"), 5.0);
+    }
+
+    #[test]
+    fn unary_binds_looser_than_exponentiation() {
+        assert_eq!(display("-2 ^ 2"), "(-(2 ^ 2))");
+        assert_eq!(value("-2 ^ 2"), -4.0);
+    }
+
+    #[test]
+    fn exponentiation_is_right_associative() {
+        assert_eq!(display("2^3^2"), "(2 ^ (3 ^ 2))");
+        assert_eq!(value("2^3^2"), 512.0);
+        assert_eq!(value("2^2^3"), 256.0);
+        assert_eq!(value("-2^2"), -4.0);
+    }
+
+    #[test]
+    fn duplicate_parameters_are_rejected_at_the_repeat() {
+        let error = crate::parse("f(x, y, x) = x").unwrap_err();
+        assert_eq!(
+            error.error,
+            CalculatorErrorType::SyntaxError("Duplicate parameter name 'x'.".to_string())
+        );
+        assert_eq!(error.token.unwrap().column, 9);
+        assert!(crate::parse("f(x, y) = x - y").is_ok());
+    }
+
+    #[test]
+    fn percent_is_postfix_unless_an_operand_follows() {
+        assert_eq!(display("10 % 3"), "(10 % 3)");
+        assert_eq!(value("10 % 3"), 1.0);
+        assert_eq!(display("50%"), "(50%)");
+        assert_eq!(value("50%"), 0.5);
+        assert_eq!(value("200 * 15%"), 30.0);
+        assert_eq!(value("10 % (-3)"), 1.0);
+        assert_eq!(value("(50%) - 3"), -2.5);
+    }
+
+    #[test]
+    fn percent_before_any_operand_is_modulo() {
+        assert_eq!(value("10 % √4"), 0.0);
+        assert_eq!(value("10 % [3, 4][0]"), 1.0);
+        assert_eq!(display("10 % |x|"), "(10 % |x|)");
+        let variables = std::collections::HashMap::from([("x".to_string(), 4.0)]);
+        assert_eq!(
+            crate::eval_with("10 % |x|", &variables).unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(display("10 % true"), "(10 % true)");
+        // Inside an absolute value the bar closes it instead.
+        assert_eq!(value("|50%|"), 0.5);
+        assert_eq!(value("|-50%| + 1"), 1.5);
+        assert!(crate::parse("10 % not true").is_err());
+    }
+
+    #[test]
+    fn percent_before_a_sign_is_ambiguous() {
+        for source in ["10 % -3", "50% + 1"] {
+            let error = crate::parse(source).unwrap_err();
+            assert!(matches!(error.error, CalculatorErrorType::SyntaxError(_)));
+            assert_eq!(error.token.unwrap().kind, TokenType::Modulo);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_rather_than_a_stack_overflow() {
+        // Test threads get 2 MiB of stack, less than the main thread's 8 MiB
+        // that `MAX_NESTING_DEPTH` is chosen for.
+        let parse = |source: String| {
+            std::thread::Builder::new()
+                .stack_size(8 << 20)
+                .spawn(move || {
+                    crate::parse(&source)
+                        .map(|_| ())
+                        .map_err(|error| error.error)
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        let nested = |depth: usize| "(".repeat(depth) + "1" + &")".repeat(depth);
+        assert_eq!(parse(nested(MAX_NESTING_DEPTH - 1)), Ok(()));
+        assert_eq!(
+            parse(nested(MAX_NESTING_DEPTH)),
+            Err(CalculatorErrorType::NestingTooDeep)
+        );
+        for source in ["(".repeat(100_000), "-".repeat(100_000) + "1"] {
+            assert_eq!(parse(source), Err(CalculatorErrorType::NestingTooDeep));
+        }
+        // A chain with n operators is a tree n + 1 levels tall.
+        let chain = |terms: usize, operator: &str| vec!["1"; terms].join(operator);
+        assert_eq!(parse(chain(MAX_CHAIN_HEIGHT, "+")), Ok(()));
+        for operator in ["+", "*", " ", " or ", " and "] {
+            assert_eq!(
+                parse(chain(MAX_CHAIN_HEIGHT + 1, operator)),
+                Err(CalculatorErrorType::NestingTooDeep),
+                "{:?}",
+                operator
+            );
+        }
+        assert_eq!(
+            parse("x".to_string() + &"[0]".repeat(MAX_CHAIN_HEIGHT)),
+            Err(CalculatorErrorType::NestingTooDeep)
+        );
+        assert_eq!(
+            parse(format!("({}) + 1", chain(MAX_CHAIN_HEIGHT - 1, "+"))),
+            Err(CalculatorErrorType::NestingTooDeep)
+        );
+        assert_eq!(
+            parse(chain(100_000, "+")),
+            Err(CalculatorErrorType::NestingTooDeep)
+        );
+    }
+
+    #[test]
+    fn conditionals_nest_and_associate_to_the_right() {
+        assert_eq!(display("x > 0 ? x : -x"), "((x > 0) ? x : (-x))");
+        assert_eq!(display("a ? b : c ? d : e"), "(a ? b : (c ? d : e))");
+        assert_eq!(display("a ? b ? 1 : 2 : 3"), "(a ? (b ? 1 : 2) : 3)");
+    }
+
+    #[test]
+    fn whitespace_may_separate_a_function_from_its_arguments() {
+        assert!(same("sin  (  x  )", "sin(x)"));
+        let Expr::Call(call) = crate::parse("sin  (  x  )").unwrap() else {
+            panic!("expected a call");
+        };
+        assert_eq!(call.callee.lexeme, "sin");
+        assert_eq!(call.arguments.len(), 1);
+        // `paren` is the closing parenthesis, with or without arguments.
+        assert_eq!(
+            (call.paren.kind, call.paren.column),
+            (TokenType::RightParen, 12)
+        );
+        let Expr::Call(call) = crate::parse("f ( )").unwrap() else {
+            panic!("expected a call");
+        };
+        assert_eq!(
+            (call.paren.kind, call.paren.column),
+            (TokenType::RightParen, 5)
+        );
+    }
+
+    #[test]
+    fn a_bare_function_name_is_a_variable() {
+        assert!(matches!(crate::parse("sin").unwrap(), Expr::Variable(_)));
+    }
+
+    fn parser(source: &str) -> Parser {
+        Parser::new(
+            scanner::Scanner::new(source.to_string())
+                .scan_tokens()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn programs_separate_expressions_with_semicolons() {
+        let program = parser("x = 3; y = 4; x^2 + y^2;").program().unwrap();
+        let displayed: Vec<_> = program.iter().map(Expr::display).collect();
+        assert_eq!(displayed, ["x = 3", "y = 4", "((x ^ 2) + (y ^ 2))"]);
+        let errors = parser("1 + ; 2 * ; 3").program().unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn parse_accepts_a_single_expression() {
+        assert_eq!(parser("1 + 2").parse().unwrap().display(), "(1 + 2)");
+        let errors = parser("1; 2").parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error, CalculatorErrorType::AdditionalCodeAfterEnd);
+        assert_eq!(errors[0].token.as_ref().unwrap().kind, TokenType::Semicolon);
+        assert_eq!(parser("1 + ) 2 * )").parse().unwrap_err().len(), 2);
+    }
+
+    #[test]
+    fn stray_dots_are_unexpected_tokens() {
+        for source in ["1.2.3", "1..5", "3 . 5", "1."] {
+            let error = crate::parse(source).unwrap_err();
+            assert_eq!(
+                error.error,
+                CalculatorErrorType::UnexpectedToken(TokenType::Dot),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn indexing_binds_tightest_and_repeats() {
+        assert_eq!(display("v[0]^2"), "(v[0] ^ 2)");
+        assert_eq!(display("-v[0]"), "(-v[0])");
+        assert_eq!(display("2v[1]"), "(2 * v[1])");
+        assert!(same("2v[1]", "2 * v[1]"));
+        assert_eq!(display("f(x)[0][1]"), "f(x)[0][1]");
+    }
+
+    #[test]
+    fn argument_lists_allow_one_trailing_comma() {
+        assert!(same("max(1, 2,)", "max(1, 2)"));
+        assert_eq!(value("max(1, 2,)"), 2.0);
+        for (source, column) in [("max(,)", 5), ("max(1,,2)", 7), ("max(1, 2,,)", 10)] {
+            let error = crate::parse(source).unwrap_err();
+            assert_eq!(
+                error.error,
+                CalculatorErrorType::ExpectedExpression,
+                "{}",
+                source
+            );
+            assert_eq!(error.token.unwrap().column, column, "{}", source);
+        }
+    }
+
+    #[test]
+    fn structural_equality_ignores_positions_but_not_groupings() {
+        assert!(same("2+3", "2 + 3"));
+        assert!(crate::parse("2+3").unwrap() != crate::parse("2 + 3").unwrap());
+        assert!(!same("2 + 3", "(2 + 3)"));
+        assert!(!same("2 + 3", "3 + 2"));
+        assert!(!same("2 + 3", "2 - 3"));
+        assert!(same("f(x, y) = x y", "f(x,y)=x*y"));
+        assert!(!same("f(x, y) = x", "f(y, x) = x"));
+    }
+}
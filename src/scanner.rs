@@ -1,8 +1,12 @@
+use crate::errors::{CalculatorError, CalculatorErrorType};
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
     // Math operators.
     Plus,
     Minus,
@@ -10,19 +14,47 @@ pub enum TokenType {
     Slash,
     Modulo,
     Caret,
+    Bang,
+    /// `|`, which both opens and closes an absolute value.
+    Bar,
+    /// `√`, a prefix square root.
+    Root,
+    /// A run of superscript digits such as `²`, with the exponent as its
+    /// literal.
+    Superscript,
+    Equal,
+    // Comparison operators.
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    BangEqual,
+    // Keywords.
+    And,
+    Or,
+    Not,
+    True,
+    False,
+    /// `?` and `:` of a conditional expression.
+    Question,
+    Colon,
     Comma,
+    /// Separates the expressions of a program, as in `x = 3; x^2`.
+    Semicolon,
     Dot,
     Identifier,
     Number,
     Eof,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Token {
     pub kind: TokenType,
     pub lexeme: String,
     pub literal: Option<f64>,
     pub line: usize,
+    pub column: usize,
 }
 
 impl Clone for Token {
@@ -32,134 +64,546 @@ impl Clone for Token {
             lexeme: self.lexeme.clone(),
             literal: self.literal,
             line: self.line,
+            column: self.column,
         }
     }
 }
 
+/// Turns source text into tokens. The scanner is an iterator that yields
+/// tokens lazily and stops after the first error or the `Eof` token.
 pub struct Scanner {
-    source: String,
-    tokens: Vec<Token>,
+    /// The source is stored as chars so `start` and `current` can index it
+    /// directly instead of re-walking the string.
+    source: Vec<char>,
+    /// The token produced by the last call to `scan_token`, if any.
+    pending: Option<Token>,
+    /// Whether the last token produced was a number, after which a `.`
+    /// can't start another one.
+    after_number: bool,
+    finished: bool,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
         Scanner {
-            source,
-            tokens: Vec::new(),
+            source: source.chars().collect(),
+            pending: None,
+            after_number: false,
+            finished: false,
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
-        }
-
-        self.tokens.push(Token {
-            kind: TokenType::Eof,
-            lexeme: "".to_string(),
-            literal: None,
-            line: self.line,
-        });
-
-        (*self.tokens).to_vec()
+    /// Scans the whole source, collecting every token up to and including
+    /// `Eof`.
+    pub fn scan_tokens(&mut self) -> Result<Vec<Token>, CalculatorError> {
+        self.collect()
     }
 
-    fn scan_token(&mut self) {
+    fn scan_token(&mut self) -> Result<(), CalculatorError> {
         let c = self.advance();
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             '+' => self.add_token(TokenType::Plus),
             '-' => self.add_token(TokenType::Minus),
             '*' => self.add_token(TokenType::Star),
-            '/' => self.add_token(TokenType::Slash),
+            '/' => {
+                if self.peek() == '/' {
+                    self.comment();
+                } else {
+                    self.add_token(TokenType::Slash);
+                }
+            }
+            '#' => self.comment(),
             '%' => self.add_token(TokenType::Modulo),
             '^' => self.add_token(TokenType::Caret),
+            '|' => self.add_token(TokenType::Bar),
+            '√' => self.add_token(TokenType::Root),
+            '⁻' | '⁰' | '¹' | '²' | '³' | '⁴' | '⁵' | '⁶' | '⁷' | '⁸' | '⁹' => {
+                self.superscript()?
+            }
+            '!' => {
+                let kind = if self.match_next('=') {
+                    TokenType::BangEqual
+                } else {
+                    TokenType::Bang
+                };
+                self.add_token(kind);
+            }
+            '=' => {
+                let kind = if self.match_next('=') {
+                    TokenType::EqualEqual
+                } else {
+                    TokenType::Equal
+                };
+                self.add_token(kind);
+            }
+            '<' => {
+                let kind = if self.match_next('=') {
+                    TokenType::LessEqual
+                } else {
+                    TokenType::Less
+                };
+                self.add_token(kind);
+            }
+            '>' => {
+                let kind = if self.match_next('=') {
+                    TokenType::GreaterEqual
+                } else {
+                    TokenType::Greater
+                };
+                self.add_token(kind);
+            }
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
+            ';' => self.add_token(TokenType::Semicolon),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
+            // `1.2.3` is a mistake rather than `1.2 * .3`, so a leading-dot
+            // number can't directly follow another number.
+            '.' => {
+                if self.peek().is_ascii_digit() && !self.after_number {
+                    self.fraction()?;
+                } else {
+                    self.add_token(TokenType::Dot);
+                }
+            }
             ' ' | '\r' | '\t' => (),
+            '\n' => {
+                self.line += 1;
+                self.line_start = self.current;
+            }
             char => {
-                if char.is_ascii_digit() {
-                    self.number();
-                } else if char.is_alphabetic() {
+                if char == '0' && (self.peek() == 'x' || self.peek() == 'X') {
+                    self.hex_number()?;
+                } else if char == '0' && (self.peek() == 'b' || self.peek() == 'B') {
+                    self.binary_number()?;
+                } else if char == '0' && (self.peek() == 'o' || self.peek() == 'O') {
+                    self.octal_number()?;
+                } else if char.is_ascii_digit() {
+                    self.number()?;
+                } else if char.is_alphabetic() || char == '_' {
                     self.identifier();
+                } else {
+                    return Err(self.error(CalculatorErrorType::UnexpectedCharacter(char)));
                 }
             }
         }
+        Ok(())
     }
 
-    fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() {
+    /// Scans consecutive superscript digits, optionally after a `⁻`, as a
+    /// single exponent. So `2³²` is `2^32` rather than `(2^3)^2`, and `10⁻³`
+    /// is `10^-3`.
+    fn superscript(&mut self) -> Result<(), CalculatorError> {
+        let negative = self.source[self.start] == '⁻';
+        let mut exponent = superscript_digit(self.source[self.start]).unwrap_or(0.0);
+        while let Some(digit) = superscript_digit(self.peek()) {
+            exponent = exponent * 10.0 + digit;
             self.advance();
         }
+        if negative && self.current - self.start == 1 {
+            return Err(self.error(CalculatorErrorType::InvalidNumberLiteral(
+                "Expected superscript digits after '⁻'.".to_string(),
+            )));
+        }
+        let exponent = if negative { -exponent } else { exponent };
+        self.add_token_with_literal(TokenType::Superscript, exponent);
+        Ok(())
+    }
 
-        self.add_token(TokenType::Identifier);
+    /// Skips a `#` or `//` comment up to, but not including, the end of the line.
+    fn comment(&mut self) {
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
     }
 
-    fn number(&mut self) {
-        while self.peek().is_ascii_digit() {
+    fn identifier(&mut self) {
+        // Superscript digits count as alphanumeric, but `x²` is `x` squared.
+        while (self.peek().is_alphanumeric() && superscript_digit(self.peek()).is_none())
+            || self.peek() == '_'
+        {
             self.advance();
         }
 
+        let kind = keyword(&self.lexeme()).unwrap_or(TokenType::Identifier);
+        self.add_token(kind);
+    }
+
+    fn number(&mut self) -> Result<(), CalculatorError> {
+        self.digits()?;
+
         if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
+        }
+
+        self.fraction()
+    }
 
-            while self.peek().is_ascii_digit() {
-                self.advance();
+    /// Scans the remainder of a number after its integer part, which is
+    /// also where numbers with a leading `.` such as `.5` begin.
+    fn fraction(&mut self) -> Result<(), CalculatorError> {
+        self.digits()?;
+
+        // An exponent is only consumed if it has at least one digit, so `2e`
+        // still scans as `2` followed by the identifier `e`.
+        if self.peek() == 'e' || self.peek() == 'E' {
+            let sign_offset = if self.peek_next() == '+' || self.peek_next() == '-' {
+                2
+            } else {
+                1
+            };
+            if self.peek_at(sign_offset).is_ascii_digit() {
+                for _ in 0..sign_offset {
+                    self.advance();
+                }
+                self.digits()?;
             }
         }
 
-        let number = self.source[self.start..self.current].parse::<f64>().unwrap();
+        let number = self.lexeme().replace('_', "").parse::<f64>().map_err(|_| {
+            self.error(CalculatorErrorType::InvalidNumberLiteral(
+                "Could not parse number.".to_string(),
+            ))
+        })?;
         self.add_token_with_literal(TokenType::Number, number);
+        Ok(())
+    }
+
+    /// Consumes a run of decimal digits, allowing single `_` separators
+    /// between them.
+    fn digits(&mut self) -> Result<(), CalculatorError> {
+        while self.peek().is_ascii_digit() || self.peek() == '_' {
+            if self.advance() == '_' && !self.peek().is_ascii_digit() {
+                return Err(self.error(CalculatorErrorType::InvalidNumberLiteral(
+                    "Digit separator '_' must be followed by a digit.".to_string(),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn hex_number(&mut self) -> Result<(), CalculatorError> {
+        self.radix_number(16, "hexadecimal")
+    }
+
+    fn binary_number(&mut self) -> Result<(), CalculatorError> {
+        self.radix_number(2, "binary")
+    }
+
+    fn octal_number(&mut self) -> Result<(), CalculatorError> {
+        self.radix_number(8, "octal")
+    }
+
+    /// Scans an integer literal with a two character prefix such as `0x`,
+    /// producing a `Number` token with the decoded value.
+    fn radix_number(&mut self, radix: u32, name: &str) -> Result<(), CalculatorError> {
+        // Consume the letter of the prefix.
+        let prefix = self.advance();
+
+        while self.peek().is_digit(radix) {
+            self.advance();
+        }
+
+        let lexeme = self.lexeme();
+        let digits = &lexeme[2..];
+        if digits.is_empty() {
+            return Err(
+                self.error(CalculatorErrorType::InvalidNumberLiteral(format!(
+                    "Expected {name} digits after '0{prefix}'."
+                ))),
+            );
+        }
+        if self.peek().is_ascii_digit() {
+            let digit = self.advance();
+            return Err(
+                self.error(CalculatorErrorType::InvalidNumberLiteral(format!(
+                    "Invalid digit '{digit}' in {name} literal."
+                ))),
+            );
+        }
+
+        let number = i64::from_str_radix(digits, radix).map_err(|_| {
+            self.error(CalculatorErrorType::InvalidNumberLiteral(format!(
+                "The {name} literal is too large."
+            )))
+        })?;
+        self.add_token_with_literal(TokenType::Number, number as f64);
+        Ok(())
     }
 
     fn advance(&mut self) -> char {
         self.current += 1;
-        self.source.chars().nth(self.current - 1).unwrap()
+        self.source[self.current - 1]
+    }
+
+    /// Consumes the next char only if it is `expected`.
+    fn match_next(&mut self, expected: char) -> bool {
+        if self.peek() != expected {
+            return false;
+        }
+        self.current += 1;
+        true
     }
 
     fn peek(&self) -> char {
         if self.is_at_end() {
             '\0'
         } else {
-            self.source.chars().nth(self.current).unwrap()
+            self.source[self.current]
         }
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
+        self.peek_at(1)
+    }
+
+    fn peek_at(&self, offset: usize) -> char {
+        if self.current + offset >= self.source.len() {
             '\0'
         } else {
-            self.source.chars().nth(self.current + 1).unwrap()
+            self.source[self.current + offset]
         }
     }
 
     fn add_token(&mut self, kind: TokenType) {
-        self.tokens.push(Token {
+        self.pending = Some(Token {
             kind,
-            lexeme: self.source[self.start..self.current].to_string(),
+            lexeme: self.lexeme(),
             literal: None,
             line: self.line,
+            column: self.column(),
         });
     }
 
     fn add_token_with_literal(&mut self, kind: TokenType, literal: f64) {
-        self.tokens.push(Token {
+        self.pending = Some(Token {
             kind,
-            lexeme: self.source[self.start..self.current].to_string(),
+            lexeme: self.lexeme(),
             literal: Some(literal),
             line: self.line,
+            column: self.column(),
         });
     }
 
+    fn error(&self, error: CalculatorErrorType) -> CalculatorError {
+        CalculatorError {
+            error,
+            token: Some(Token {
+                kind: TokenType::Number,
+                lexeme: self.lexeme(),
+                literal: None,
+                line: self.line,
+                column: self.column(),
+            }),
+        }
+    }
+
+    /// The 1-based column of the token currently being scanned.
+    fn column(&self) -> usize {
+        self.start - self.line_start + 1
+    }
+
+    fn lexeme(&self) -> String {
+        self.source[self.start..self.current].iter().collect()
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 }
+
+impl Iterator for Scanner {
+    type Item = Result<Token, CalculatorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        // Whitespace and comments don't produce tokens, so keep scanning
+        // until one does.
+        while !self.is_at_end() {
+            self.start = self.current;
+            if let Err(error) = self.scan_token() {
+                self.finished = true;
+                return Some(Err(error));
+            }
+            if let Some(token) = self.pending.take() {
+                self.after_number = token.kind == TokenType::Number;
+                return Some(Ok(token));
+            }
+        }
+
+        self.finished = true;
+        Some(Ok(Token {
+            kind: TokenType::Eof,
+            lexeme: "".to_string(),
+            literal: None,
+            line: self.line,
+            column: self.current - self.line_start + 1,
+        }))
+    }
+}
+
+/// The value of a superscript digit such as `²`.
+fn superscript_digit(c: char) -> Option<f64> {
+    let digit = match c {
+        '⁰' => 0,
+        '¹' => 1,
+        '²' => 2,
+        '³' => 3,
+        '⁴' => 4,
+        '⁵' => 5,
+        '⁶' => 6,
+        '⁷' => 7,
+        '⁸' => 8,
+        '⁹' => 9,
+        _ => return None,
+    };
+    Some(digit as f64)
+}
+
+/// Reserved words and the token types they scan as. Keywords can't be used
+/// as variable or function names.
+const KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", TokenType::And),
+    ("or", TokenType::Or),
+    ("not", TokenType::Not),
+    ("true", TokenType::True),
+    ("false", TokenType::False),
+];
+
+/// Whether `lexeme` is a reserved word.
+pub fn is_keyword(lexeme: &str) -> bool {
+    keyword(lexeme).is_some()
+}
+
+/// The token type of a reserved word, or `None` if `lexeme` is an ordinary
+/// identifier.
+fn keyword(lexeme: &str) -> Option<TokenType> {
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == lexeme)
+        .map(|(_, kind)| kind.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Result<Vec<Token>, CalculatorError> {
+        Scanner::new(source.to_string()).scan_tokens()
+    }
+
+    fn number(source: &str) -> f64 {
+        let tokens = scan(source).unwrap();
+        assert_eq!(tokens.len(), 2, "expected a single number in {:?}", source);
+        assert_eq!(tokens[0].kind, TokenType::Number);
+        tokens[0].literal.unwrap()
+    }
+
+    fn error(source: &str) -> CalculatorErrorType {
+        scan(source).unwrap_err().error
+    }
+
+    #[test]
+    fn every_radix_scans_its_own_digits() {
+        for (source, expected) in [
+            ("17", 17.0),
+            ("0x1F", 31.0),
+            ("0XfF", 255.0),
+            ("0o17", 15.0),
+            ("0O17", 15.0),
+            ("0b101", 5.0),
+            ("0B11", 3.0),
+            ("017", 17.0),
+        ] {
+            assert_eq!(number(source), expected, "{}", source);
+        }
+        for source in ["0x", "0o", "0b", "0o18", "0o9", "0b2", "0xg"] {
+            assert!(
+                matches!(error(source), CalculatorErrorType::InvalidNumberLiteral(_)),
+                "{}",
+                source
+            );
+        }
+    }
+
+    /// The kinds and lexemes of every token before `Eof`.
+    fn tokens(source: &str) -> Vec<(TokenType, String)> {
+        let mut tokens = scan(source).unwrap();
+        assert_eq!(tokens.pop().unwrap().kind, TokenType::Eof);
+        tokens
+            .into_iter()
+            .map(|token| (token.kind, token.lexeme))
+            .collect()
+    }
+
+    #[test]
+    fn a_dot_after_a_number_never_starts_another_number() {
+        let dot = (TokenType::Dot, ".".to_string());
+        assert_eq!(tokens("1.2.3")[1], dot);
+        assert_eq!(tokens("1.2 .3")[1], dot);
+        assert_eq!(tokens("1..5")[1], dot);
+        assert_eq!(number(".5"), 0.5);
+        assert_eq!(tokens("x.5")[1], (TokenType::Number, ".5".to_string()));
+    }
+
+    #[test]
+    fn numbers_next_to_multibyte_characters_keep_their_digits() {
+        let number = |lexeme: &str| (TokenType::Number, lexeme.to_string());
+        let pi = (TokenType::Identifier, "π".to_string());
+        assert_eq!(tokens("2π"), [number("2"), pi.clone()]);
+        assert_eq!(tokens("2.5π"), [number("2.5"), pi]);
+        assert_eq!(
+            tokens("1.5²"),
+            [number("1.5"), (TokenType::Superscript, "²".to_string())]
+        );
+        assert_eq!(tokens("√2.25")[1], number("2.25"));
+        // Columns count characters, not bytes.
+        assert_eq!(scan("π + 1.5").unwrap()[2].column, 5);
+    }
+
+    #[test]
+    fn a_number_before_pi_evaluates_as_a_product() {
+        let expected = crate::value::Value::Number(2.0 * std::f64::consts::PI + 1.0);
+        assert_eq!(crate::eval("2π + 1").unwrap(), expected);
+    }
+
+    #[test]
+    fn malformed_number_literals_are_errors_rather_than_panics() {
+        // `1e` is `1` followed by the constant `e`, and `._5` is a dot
+        // followed by the identifier `_5`.
+        for (source, ok) in [
+            ("1e", true),
+            ("1e+", true),
+            ("0x", false),
+            ("1_", false),
+            ("1__2", false),
+            ("1e999", true),
+            ("0xFFFFFFFFFFFFFFFFFFFF", false),
+            ("._5", true),
+            ("2⁻", false),
+        ] {
+            assert_eq!(scan(source).is_ok(), ok, "{}", source);
+            if !ok {
+                assert!(
+                    matches!(error(source), CalculatorErrorType::InvalidNumberLiteral(_)),
+                    "{}",
+                    source
+                );
+            }
+        }
+        assert_eq!(number("1e999"), f64::INFINITY);
+    }
+}
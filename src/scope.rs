@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use crate::value::Value;
+
+/// A set of variable bindings, optionally nested inside a parent scope.
+///
+/// Function calls evaluate their body in a child scope holding the
+/// parameters, so parameters shadow globals without overwriting them.
+#[derive(Clone, Default)]
+pub struct Scope {
+    pub variables: HashMap<String, Value>,
+    parent: Option<Box<Scope>>,
+}
+
+impl Scope {
+    pub fn new() -> Scope {
+        Scope::default()
+    }
+
+    /// Creates an empty scope whose lookups fall back to `parent`.
+    pub fn with_parent(parent: Scope) -> Scope {
+        Scope {
+            variables: HashMap::new(),
+            parent: Some(Box::new(parent)),
+        }
+    }
+
+    /// Removes this scope, returning its parent, or `None` for the global
+    /// scope.
+    pub fn into_parent(self) -> Option<Scope> {
+        self.parent.map(|parent| *parent)
+    }
+
+    /// Looks `name` up in this scope, then in each parent in turn.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.variables.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.get(name),
+        }
+    }
+
+    /// Every name visible from this scope, including those in parents.
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<_> = self.variables.keys().collect();
+        if let Some(parent) = &self.parent {
+            names.extend(parent.names());
+        }
+        names
+    }
+
+    /// Binds `name` in this scope, shadowing any binding in a parent.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+}
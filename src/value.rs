@@ -0,0 +1,109 @@
+use std::fmt::Display;
+
+use crate::{
+    errors::{CalculatorError, CalculatorErrorType},
+    scanner::Token,
+};
+
+/// The result of evaluating an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    /// A list of numbers such as `[1, 2, 3]`, which arithmetic operators
+    /// apply to elementwise.
+    Vector(Vec<f64>),
+    /// The result of a definition, which has no meaningful value.
+    Unit,
+}
+
+impl Value {
+    /// Returns the number held by this value, or a type error located at
+    /// `token` if it is not a number.
+    pub fn to_number(&self, token: &Token) -> Result<f64, CalculatorError> {
+        match self {
+            Value::Number(number) => Ok(*number),
+            _ => Err(CalculatorError {
+                error: CalculatorErrorType::TypeError(format!(
+                    "Expected a number but found {}.",
+                    self.type_name()
+                )),
+                token: Some(token.clone()),
+            }),
+        }
+    }
+
+    /// Returns whether this value counts as true, or a type error located at
+    /// `token` if it has no truth value. Numbers are true when nonzero, and
+    /// NaN, which isn't any number, is false.
+    pub fn to_bool(&self, token: &Token) -> Result<bool, CalculatorError> {
+        match self {
+            Value::Bool(bool) => Ok(*bool),
+            Value::Number(number) => Ok(*number != 0.0 && !number.is_nan()),
+            _ => Err(CalculatorError {
+                error: CalculatorErrorType::TypeError(format!(
+                    "Expected a boolean or a number but found {}.",
+                    self.type_name()
+                )),
+                token: Some(token.clone()),
+            }),
+        }
+    }
+
+    /// Returns the vector held by this value, or a type error located at
+    /// `token` if it is not a vector.
+    pub fn to_vector(&self, token: &Token) -> Result<&[f64], CalculatorError> {
+        match self {
+            Value::Vector(vector) => Ok(vector),
+            _ => Err(CalculatorError {
+                error: CalculatorErrorType::TypeError(format!(
+                    "Expected a vector but found {}.",
+                    self.type_name()
+                )),
+                token: Some(token.clone()),
+            }),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Bool(_) => "a boolean",
+            Value::Vector(_) => "a vector",
+            Value::Unit => "nothing",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // `f64`'s `Display` already omits the `.0` on integral values.
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Bool(bool) => write!(f, "{}", bool),
+            Value::Vector(vector) => {
+                let elements: Vec<_> = vector.iter().map(f64::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(vector: Vec<f64>) -> Self {
+        Value::Vector(vector)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(bool: bool) -> Self {
+        Value::Bool(bool)
+    }
+}
@@ -0,0 +1,80 @@
+//! An entry point for running the calculator in a browser.
+//!
+//! Built with `cargo rustc --lib --release --target wasm32-unknown-unknown
+//! --crate-type cdylib`, the library exports plain C ABI functions that
+//! JavaScript can call without a bindings generator: write the UTF-8 source
+//! into memory from `calculator_alloc`, call `calculator_eval`, then read
+//! the result through `calculator_output_ptr` and `calculator_output_len`.
+//! `web/index.html` shows the whole round trip. The crate type is chosen on
+//! the command line so native builds only produce the `rlib`.
+
+use crate::format::{self, FormatOptions};
+
+/// Evaluates `source` with a fresh interpreter, returning the formatted
+/// result, or the error rendered with a caret under the offending token.
+pub fn eval_js(source: &str) -> Result<String, String> {
+    match crate::eval(source) {
+        Ok(value) => Ok(format::format_value(&value, &FormatOptions::default())),
+        Err(error) => Err(error.render(source)),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// The result or error text of the last `calculator_eval`.
+        static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    /// Reserves `len` bytes for JavaScript to write the source into.
+    #[no_mangle]
+    pub extern "C" fn calculator_alloc(len: usize) -> *mut u8 {
+        let mut buffer = Vec::<u8>::with_capacity(len);
+        let ptr = buffer.as_mut_ptr();
+        std::mem::forget(buffer);
+        ptr
+    }
+
+    /// Frees memory returned by `calculator_alloc`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `len` must come from a single call to `calculator_alloc`.
+    #[no_mangle]
+    pub unsafe extern "C" fn calculator_dealloc(ptr: *mut u8, len: usize) {
+        drop(Vec::from_raw_parts(ptr, 0, len));
+    }
+
+    /// Evaluates the `len` bytes of UTF-8 source at `ptr`, returning 1 on
+    /// success and 0 on error. Either way the text to show is left in the
+    /// output buffer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` initialized bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn calculator_eval(ptr: *const u8, len: usize) -> u32 {
+        let source = std::slice::from_raw_parts(ptr, len);
+        let (success, output) = match std::str::from_utf8(source) {
+            Ok(source) => match super::eval_js(source) {
+                Ok(result) => (1, result),
+                Err(error) => (0, error),
+            },
+            Err(_) => (0, "The source is not valid UTF-8.".to_string()),
+        };
+        OUTPUT.with(|buffer| *buffer.borrow_mut() = output);
+        success
+    }
+
+    #[no_mangle]
+    pub extern "C" fn calculator_output_ptr() -> *const u8 {
+        OUTPUT.with(|buffer| buffer.borrow().as_ptr())
+    }
+
+    #[no_mangle]
+    pub extern "C" fn calculator_output_len() -> usize {
+        OUTPUT.with(|buffer| buffer.borrow().len())
+    }
+}